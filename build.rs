@@ -6,6 +6,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     tonic_build::configure()
         .file_descriptor_set_path(out_dir.join("service_descriptor.bin"))
-        .compile_protos(&["proto/service.proto"], &["proto"])?;
+        .compile_protos(&["proto/service.proto", "proto/google/rpc/status.proto", "proto/google/rpc/error_details.proto"], &["proto"])?;
     Ok(())
 }
@@ -0,0 +1,302 @@
+use crate::error::app::AppError;
+use crate::model::key_policy::KeyPolicy;
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+const LISTEN_ADDR_VARIABLE: &str = "PARAPLUIE_LISTEN_ADDR";
+const METRICS_ADDR_VARIABLE: &str = "PARAPLUIE_METRICS_ADDR";
+const DATABASE_PATH_VARIABLE: &str = "PARAPLUIE_DATABASE_PATH";
+const LOG_FILTER_VARIABLE: &str = "PARAPLUIE_LOG";
+const LOG_FORMAT_VARIABLE: &str = "PARAPLUIE_LOG_FORMAT";
+const TLS_CERT_VARIABLE: &str = "PARAPLUIE_TLS_CERT";
+const TLS_KEY_VARIABLE: &str = "PARAPLUIE_TLS_KEY";
+const TLS_CLIENT_CA_VARIABLE: &str = "PARAPLUIE_TLS_CLIENT_CA";
+const TOKEN_FILE_VARIABLE: &str = "PARAPLUIE_TOKEN_FILE";
+const ACL_FILE_VARIABLE: &str = "PARAPLUIE_ACL_FILE";
+const RATE_LIMIT_VARIABLE: &str = "PARAPLUIE_RATE_LIMIT";
+const RATE_LIMIT_BURST_VARIABLE: &str = "PARAPLUIE_RATE_LIMIT_BURST";
+const MAX_QUEUE_DEPTH_VARIABLE: &str = "PARAPLUIE_MAX_QUEUE_DEPTH";
+const MAX_QUEUE_WAIT_VARIABLE: &str = "PARAPLUIE_MAX_QUEUE_WAIT_MS";
+const IDEMPOTENCY_WINDOW_VARIABLE: &str = "PARAPLUIE_IDEMPOTENCY_WINDOW_S";
+const MAX_PARTITION_KEY_LENGTH_VARIABLE: &str = "PARAPLUIE_MAX_PARTITION_KEY_BYTES";
+const MAX_SORT_KEY_LENGTH_VARIABLE: &str = "PARAPLUIE_MAX_SORT_KEY_BYTES";
+const NORMALIZE_KEYS_VARIABLE: &str = "PARAPLUIE_NORMALIZE_KEYS";
+const MAX_VALUE_SIZE_VARIABLE: &str = "PARAPLUIE_MAX_VALUE_BYTES";
+const MAX_LARGE_VALUE_SIZE_VARIABLE: &str = "PARAPLUIE_MAX_LARGE_VALUE_BYTES";
+const LARGE_VALUE_BUFFER_SIZE_VARIABLE: &str = "PARAPLUIE_LARGE_VALUE_BUFFER_BYTES";
+const MAX_MESSAGE_SIZE_VARIABLE: &str = "PARAPLUIE_MAX_MESSAGE_BYTES";
+const DELETE_BATCH_SIZE_VARIABLE: &str = "PARAPLUIE_DELETE_BATCH_SIZE";
+const MAX_DELETE_BATCHES_VARIABLE: &str = "PARAPLUIE_MAX_DELETE_BATCHES";
+const TOMBSTONE_RETENTION_VARIABLE: &str = "PARAPLUIE_TOMBSTONE_RETENTION_S";
+const MAX_SNAPSHOTS_VARIABLE: &str = "PARAPLUIE_MAX_SNAPSHOTS";
+const SNAPSHOT_LEASE_VARIABLE: &str = "PARAPLUIE_SNAPSHOT_LEASE_S";
+#[cfg(feature = "otel")]
+const OTLP_ENDPOINT_VARIABLE: &str = "PARAPLUIE_OTLP_ENDPOINT";
+
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:50051";
+const DEFAULT_METRICS_ADDR: &str = "0.0.0.0:9090";
+const DEFAULT_DATABASE_PATH: &str = "/tmp/db.sqlite";
+const DEFAULT_LOG_FILTER: &str = "info";
+const DEFAULT_LOG_FORMAT: &str = "text";
+// NOTE: Capacity of the queue of the processor when no maximum depth is set, past which requests wait
+// for a slot instead of being rejected.
+const DEFAULT_QUEUE_CAPACITY: usize = 32;
+const DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_MAX_VALUE_SIZE: usize = 1024 * 1024;
+const DEFAULT_MAX_LARGE_VALUE_SIZE: usize = 64 * 1024 * 1024;
+const DEFAULT_LARGE_VALUE_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+// NOTE: The default of tonic, which must leave room for a value of the maximum size and its keys.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+const DEFAULT_DELETE_BATCH_SIZE: usize = 1000;
+const DEFAULT_MAX_DELETE_BATCHES: usize = 100;
+const DEFAULT_TOMBSTONE_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const DEFAULT_MAX_SNAPSHOTS: usize = 16;
+const DEFAULT_SNAPSHOT_LEASE: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// PEM files used to serve gRPC over TLS.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// CA bundle used to verify client certificates; mutual TLS is required when set.
+    pub client_ca_path: Option<PathBuf>,
+}
+
+/// Token bucket applied to each client and RPC.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitConfig {
+    /// Requests per second.
+    pub rate: f64,
+    /// Requests allowed in a burst, after a period of inactivity.
+    pub burst: f64,
+}
+
+/// Maximum sizes of the values written by clients, in bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct ValueLimits {
+    /// Maximum size of each value of a `Set`.
+    pub max_value_size: usize,
+    /// Maximum size of a value written in chunks by `PutLarge`.
+    pub max_large_value_size: usize,
+    /// Bytes of the chunks of `PutLarge` and `GetLarge` held in memory at once, across all the streams.
+    pub large_value_buffer_size: usize,
+}
+
+/// How `DeleteRange` splits its work, so that deleting a large range does not block the processor.
+#[derive(Clone, Copy, Debug)]
+pub struct DeleteLimits {
+    /// Items deleted by each task of the processor, in a transaction of its own.
+    pub batch_size: usize,
+    /// Batches deleted by a single `DeleteRange`, after which it returns a continuation.
+    pub max_batches: usize,
+}
+
+/// Bounds the snapshots opened by `BeginSnapshot`, each of which holds a connection and delays WAL
+/// checkpoints while it is open.
+#[derive(Clone, Copy, Debug)]
+pub struct SnapshotLimits {
+    /// Snapshots open at the same time, over which `BeginSnapshot` fails.
+    pub max_snapshots: usize,
+    /// How long a snapshot is kept open without being read.
+    pub lease: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub listen_addr: SocketAddr,
+    pub metrics_addr: SocketAddr,
+    pub database_path: PathBuf,
+    /// Filter directives, in the `tracing_subscriber::EnvFilter` syntax (e.g. `info,parapluie=debug`).
+    pub log_filter: String,
+    pub log_format: LogFormat,
+    /// Plaintext is served when unset.
+    pub tls: Option<TlsConfig>,
+    /// File of the bearer tokens accepted by the server; authentication is disabled when unset.
+    pub token_file: Option<PathBuf>,
+    /// File of the permissions of each identity; every request is allowed when unset.
+    pub acl_file: Option<PathBuf>,
+    /// Requests are not rate limited when unset.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Requests are rejected when this many tasks are already waiting for the processor; at least 1.
+    pub max_queue_depth: Option<usize>,
+    /// Tasks that waited longer than this for the processor are rejected without being executed.
+    pub max_queue_wait: Option<Duration>,
+    /// How long the result of a `Set` is replayed for retries carrying the same idempotency key.
+    pub idempotency_window: Duration,
+    /// Rules partition and sort keys must follow.
+    pub key_policy: KeyPolicy,
+    pub value_limits: ValueLimits,
+    /// Maximum size of the gRPC messages received and sent, in bytes.
+    pub max_message_size: usize,
+    pub delete_limits: DeleteLimits,
+    /// How long deleted items are kept as tombstones, during which they can be restored.
+    pub tombstone_retention: Duration,
+    pub snapshot_limits: SnapshotLimits,
+    /// OTLP/gRPC endpoint traces are exported to (e.g. `http://localhost:4317`); disabled if unset.
+    #[cfg(feature = "otel")]
+    pub otlp_endpoint: Option<String>,
+}
+
+impl Config {
+    /// Reads the configuration from the environment, falling back to defaults for unset variables.
+    pub fn from_env() -> Result<Self, AppError> {
+        let listen_addr = variable(LISTEN_ADDR_VARIABLE, DEFAULT_LISTEN_ADDR).parse()?;
+        let metrics_addr = variable(METRICS_ADDR_VARIABLE, DEFAULT_METRICS_ADDR).parse()?;
+        let database_path = variable(DATABASE_PATH_VARIABLE, DEFAULT_DATABASE_PATH).into();
+        let log_filter = variable(LOG_FILTER_VARIABLE, DEFAULT_LOG_FILTER);
+        let log_format = match variable(LOG_FORMAT_VARIABLE, DEFAULT_LOG_FORMAT).as_str() {
+            "text" => LogFormat::Text,
+            "json" => LogFormat::Json,
+            other => return Err(invalid(LOG_FORMAT_VARIABLE, other)),
+        };
+
+        let tls = match (env::var(TLS_CERT_VARIABLE), env::var(TLS_KEY_VARIABLE)) {
+            (Ok(cert_path), Ok(key_path)) => Some(TlsConfig {
+                cert_path: cert_path.into(),
+                key_path: key_path.into(),
+                client_ca_path: env::var(TLS_CLIENT_CA_VARIABLE).ok().map(PathBuf::from),
+            }),
+            (Err(_), Err(_)) if env::var(TLS_CLIENT_CA_VARIABLE).is_err() => None,
+            _ => return Err(AppError::InvalidConfiguration(format!(
+                "{} and {} must be set together, and are required by {}", TLS_CERT_VARIABLE, TLS_KEY_VARIABLE, TLS_CLIENT_CA_VARIABLE,
+            ))),
+        };
+
+        let rate_limit = parsed::<f64>(RATE_LIMIT_VARIABLE)?
+            .map(|rate| -> Result<RateLimitConfig, AppError> {
+                let burst = parsed::<f64>(RATE_LIMIT_BURST_VARIABLE)?.unwrap_or(rate);
+                Ok(RateLimitConfig { rate, burst })
+            })
+            .transpose()?;
+
+        let default_key_policy = KeyPolicy::default();
+        let key_policy = KeyPolicy {
+            max_partition_key_length: parsed(MAX_PARTITION_KEY_LENGTH_VARIABLE)?.unwrap_or(default_key_policy.max_partition_key_length),
+            max_sort_key_length: parsed(MAX_SORT_KEY_LENGTH_VARIABLE)?.unwrap_or(default_key_policy.max_sort_key_length),
+            normalize: parsed(NORMALIZE_KEYS_VARIABLE)?.unwrap_or(default_key_policy.normalize),
+        };
+
+        Ok(Self {
+            listen_addr,
+            metrics_addr,
+            database_path,
+            log_filter,
+            log_format,
+            tls,
+            token_file: env::var(TOKEN_FILE_VARIABLE).ok().map(PathBuf::from),
+            acl_file: env::var(ACL_FILE_VARIABLE).ok().map(PathBuf::from),
+            rate_limit,
+            max_queue_depth: positive(MAX_QUEUE_DEPTH_VARIABLE)?,
+            max_queue_wait: parsed(MAX_QUEUE_WAIT_VARIABLE)?.map(Duration::from_millis),
+            idempotency_window: parsed(IDEMPOTENCY_WINDOW_VARIABLE)?.map(Duration::from_secs).unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW),
+            key_policy,
+            value_limits: ValueLimits {
+                max_value_size: parsed(MAX_VALUE_SIZE_VARIABLE)?.unwrap_or(DEFAULT_MAX_VALUE_SIZE),
+                max_large_value_size: parsed(MAX_LARGE_VALUE_SIZE_VARIABLE)?.unwrap_or(DEFAULT_MAX_LARGE_VALUE_SIZE),
+                large_value_buffer_size: positive(LARGE_VALUE_BUFFER_SIZE_VARIABLE)?.unwrap_or(DEFAULT_LARGE_VALUE_BUFFER_SIZE),
+            },
+            max_message_size: parsed(MAX_MESSAGE_SIZE_VARIABLE)?.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE),
+            delete_limits: DeleteLimits {
+                batch_size: positive(DELETE_BATCH_SIZE_VARIABLE)?.unwrap_or(DEFAULT_DELETE_BATCH_SIZE),
+                max_batches: positive(MAX_DELETE_BATCHES_VARIABLE)?.unwrap_or(DEFAULT_MAX_DELETE_BATCHES),
+            },
+            tombstone_retention: parsed(TOMBSTONE_RETENTION_VARIABLE)?.map(Duration::from_secs).unwrap_or(DEFAULT_TOMBSTONE_RETENTION),
+            snapshot_limits: SnapshotLimits {
+                max_snapshots: parsed(MAX_SNAPSHOTS_VARIABLE)?.unwrap_or(DEFAULT_MAX_SNAPSHOTS),
+                lease: parsed(SNAPSHOT_LEASE_VARIABLE)?.map(Duration::from_secs).unwrap_or(DEFAULT_SNAPSHOT_LEASE),
+            },
+            #[cfg(feature = "otel")]
+            otlp_endpoint: env::var(OTLP_ENDPOINT_VARIABLE).ok(),
+        })
+    }
+}
+
+impl Config {
+    /// Capacity of the queue of the processor, which holds `max_queue_depth` tasks so that requests are
+    /// rejected once it is reached.
+    pub fn queue_capacity(&self) -> usize {
+        self.max_queue_depth.unwrap_or(DEFAULT_QUEUE_CAPACITY)
+    }
+}
+
+fn variable(name: &str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn parsed<T: FromStr>(name: &str) -> Result<Option<T>, AppError> {
+    env::var(name)
+        .ok()
+        .map(|value| value.parse().map_err(|_| invalid(name, &value)))
+        .transpose()
+}
+
+/// Parses a count that must not be zero.
+fn positive(name: &str) -> Result<Option<usize>, AppError> {
+    match parsed(name)? {
+        Some(0) => Err(invalid(name, "0")),
+        value => Ok(value),
+    }
+}
+
+fn invalid(name: &str, value: &str) -> AppError {
+    AppError::InvalidConfiguration(format!("{}: unsupported value {:?}", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // NOTE: The environment is shared by the tests, which run in parallel.
+    static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+    /// Reads the configuration with a variable set.
+    fn from_env_with(name: &str, value: &str) -> Result<Config, AppError> {
+        let _environment = ENVIRONMENT.lock().unwrap();
+        env::set_var(name, value);
+        let config = Config::from_env();
+        env::remove_var(name);
+        config
+    }
+
+    fn assert_invalid(name: &str, value: &str) {
+        match from_env_with(name, value) {
+            Err(AppError::InvalidConfiguration(message)) => assert!(message.starts_with(name), "{}", message),
+            result => panic!("{}={} was accepted: {:?}", name, value, result),
+        }
+    }
+
+    #[test]
+    fn queue_capacity_is_the_maximum_queue_depth() {
+        let config = from_env_with(MAX_QUEUE_DEPTH_VARIABLE, "100").unwrap();
+
+        assert_eq!(config.queue_capacity(), 100);
+        assert_invalid(MAX_QUEUE_DEPTH_VARIABLE, "0");
+    }
+
+    // NOTE: Batches of no items would never finish purging tombstones, and would delete nothing.
+    #[test]
+    fn delete_batches_are_not_empty() {
+        let config = from_env_with(DELETE_BATCH_SIZE_VARIABLE, "1").unwrap();
+
+        assert_eq!(config.delete_limits.batch_size, 1);
+        assert_invalid(DELETE_BATCH_SIZE_VARIABLE, "0");
+    }
+
+    #[test]
+    fn delete_ranges_run_at_least_one_batch() {
+        let config = from_env_with(MAX_DELETE_BATCHES_VARIABLE, "1").unwrap();
+
+        assert_eq!(config.delete_limits.max_batches, 1);
+        assert_invalid(MAX_DELETE_BATCHES_VARIABLE, "0");
+    }
+}
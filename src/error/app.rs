@@ -12,6 +12,11 @@ pub enum AppError {
     InvalidAddress(AddrParseError),
     TracingSetupError(SetGlobalDefaultError),
     ReflectionServiceSetupError(tonic_reflection::server::Error),
+    MetricsSetupError(prometheus::Error),
+    IoError(std::io::Error),
+    InvalidConfiguration(String),
+    #[cfg(feature = "otel")]
+    TraceExporterSetupError(opentelemetry::trace::TraceError),
 }
 
 impl Display for AppError {
@@ -24,6 +29,11 @@ impl Display for AppError {
             AppError::InvalidAddress(e) => write!(f, "invalid address: {}", e),
             AppError::TracingSetupError(e) => write!(f, "tracing setup error: {}", e),
             AppError::ReflectionServiceSetupError(e) => write!(f, "reflection service setup error: {}", e),
+            AppError::MetricsSetupError(e) => write!(f, "metrics setup error: {}", e),
+            AppError::IoError(e) => write!(f, "io error: {}", e),
+            AppError::InvalidConfiguration(e) => write!(f, "invalid configuration: {}", e),
+            #[cfg(feature = "otel")]
+            AppError::TraceExporterSetupError(e) => write!(f, "trace exporter setup error: {}", e),
         }
     }
 }
@@ -38,6 +48,11 @@ impl std::error::Error for AppError {
             AppError::InvalidAddress(e) => Some(e),
             AppError::TracingSetupError(e) => Some(e),
             AppError::ReflectionServiceSetupError(e) => Some(e),
+            AppError::MetricsSetupError(e) => Some(e),
+            AppError::IoError(e) => Some(e),
+            AppError::InvalidConfiguration(_) => None,
+            #[cfg(feature = "otel")]
+            AppError::TraceExporterSetupError(e) => Some(e),
         }
     }
 }
@@ -86,4 +101,29 @@ impl From<tonic_reflection::server::Error> for AppError {
     fn from(e: tonic_reflection::server::Error) -> Self {
         AppError::ReflectionServiceSetupError(e)
     }
+}
+
+impl From<prometheus::Error> for AppError {
+    fn from(e: prometheus::Error) -> Self {
+        AppError::MetricsSetupError(e)
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::IoError(e)
+    }
+}
+
+impl From<tracing_subscriber::filter::ParseError> for AppError {
+    fn from(e: tracing_subscriber::filter::ParseError) -> Self {
+        AppError::InvalidConfiguration(format!("invalid log filter: {}", e))
+    }
+}
+
+#[cfg(feature = "otel")]
+impl From<opentelemetry::trace::TraceError> for AppError {
+    fn from(e: opentelemetry::trace::TraceError) -> Self {
+        AppError::TraceExporterSetupError(e)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,34 @@
+use std::fmt::{Display, Formatter};
+
+/// Errors raised by the `counter_add` SQL function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterError {
+    Overflow,
+    NotAnInteger,
+}
+
+impl CounterError {
+    /// Recovers the error raised by the SQL function, which SQLite only reports as a generic error
+    /// with its message.
+    pub fn from_sqlite(error: &rusqlite::Error) -> Option<Self> {
+        match error {
+            rusqlite::Error::SqliteFailure(_, Some(message)) => {
+                [CounterError::Overflow, CounterError::NotAnInteger]
+                    .into_iter()
+                    .find(|counter_error| counter_error.to_string() == *message)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for CounterError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            CounterError::Overflow => write!(f, "counter overflow"),
+            CounterError::NotAnInteger => write!(f, "value is not an encoded 64-bit integer"),
+        }
+    }
+}
+
+impl std::error::Error for CounterError {}
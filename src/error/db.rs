@@ -1,10 +1,52 @@
+use crate::error::counter::CounterError;
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum DatabaseError {
     NoRemainingMessageInChannel,
     FailedToSendRequest(Box<dyn std::error::Error + Send>),
     SqliteError(rusqlite::Error),
+    QueueFull,
+    QueueWaitExceeded(Duration),
+    DeadlineExceeded,
+    Cancelled,
+    CounterError(CounterError),
+    NotFound,
+    InvalidJson(serde_json::Error),
+    PatchFailed(json_patch::PatchError),
+    /// The patched value is larger than the maximum size of a value.
+    ValueTooLarge {
+        size: usize,
+        max_size: usize,
+    },
+    /// The value of the item was written in chunks, and cannot be used by this operation.
+    LargeValue,
+    /// The item was written while its value was being read in chunks.
+    LargeValueChanged,
+    /// The snapshot was ended, or released after its lease expired.
+    SnapshotNotFound,
+    TooManySnapshots,
+    /// A write condition or write mode was not met; `index` is the position of the failed value in
+    /// a `Set`.
+    PreconditionFailed {
+        index: Option<usize>,
+        failure: PreconditionFailure,
+    },
+    /// The idempotency key was used by a different request, or by another caller, within the
+    /// idempotency window.
+    IdempotencyKeyReused,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreconditionFailure {
+    ConditionNotMet,
+    ItemAlreadyExists,
+    ItemDoesNotExist,
+    VersionMismatch {
+        expected: u64,
+        actual: u64,
+    },
 }
 
 
@@ -14,13 +56,42 @@ impl Display for DatabaseError {
             DatabaseError::NoRemainingMessageInChannel => write!(f, "no remaining message in channel"),
             DatabaseError::FailedToSendRequest(e) => write!(f, "failed to send request: {}", e),
             DatabaseError::SqliteError(e) => write!(f, "sqlite error: {}", e),
+            DatabaseError::QueueFull => write!(f, "too many pending requests"),
+            DatabaseError::QueueWaitExceeded(waited) => write!(f, "request waited {:?} in the queue", waited),
+            DatabaseError::DeadlineExceeded => write!(f, "deadline expired before the request was executed"),
+            DatabaseError::Cancelled => write!(f, "request cancelled before it was executed"),
+            DatabaseError::CounterError(e) => write!(f, "{}", e),
+            DatabaseError::NotFound => write!(f, "not found"),
+            DatabaseError::InvalidJson(e) => write!(f, "stored value is not valid JSON: {}", e),
+            DatabaseError::PatchFailed(e) => write!(f, "failed to apply patch: {}", e),
+            DatabaseError::ValueTooLarge { size, max_size } => write!(f, "value is {} bytes long, the maximum is {}", size, max_size),
+            DatabaseError::LargeValue => write!(f, "the value was written in chunks"),
+            DatabaseError::LargeValueChanged => write!(f, "the item was written while its value was being read"),
+            DatabaseError::SnapshotNotFound => write!(f, "snapshot not found, or expired"),
+            DatabaseError::TooManySnapshots => write!(f, "too many open snapshots"),
+            DatabaseError::PreconditionFailed { failure, .. } => write!(f, "{}", failure),
+            DatabaseError::IdempotencyKeyReused => write!(f, "idempotency key already used by a different request"),
+        }
+    }
+}
+
+impl Display for PreconditionFailure {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            PreconditionFailure::ConditionNotMet => write!(f, "write condition not met"),
+            PreconditionFailure::ItemAlreadyExists => write!(f, "item already exists"),
+            PreconditionFailure::ItemDoesNotExist => write!(f, "item does not exist"),
+            PreconditionFailure::VersionMismatch { expected, actual } => write!(f, "expected version {}, found version {}", expected, actual),
         }
     }
 }
 
 impl From<rusqlite::Error> for DatabaseError {
     fn from(e: rusqlite::Error) -> Self {
-        DatabaseError::SqliteError(e)
+        match CounterError::from_sqlite(&e) {
+            Some(counter_error) => DatabaseError::CounterError(counter_error),
+            None => DatabaseError::SqliteError(e),
+        }
     }
 }
 
@@ -31,6 +102,21 @@ impl std::error::Error for DatabaseError {
             DatabaseError::NoRemainingMessageInChannel => None,
             DatabaseError::FailedToSendRequest(e) => Some(&**e),
             DatabaseError::SqliteError(e) => Some(e),
+            DatabaseError::QueueFull => None,
+            DatabaseError::QueueWaitExceeded(_) => None,
+            DatabaseError::DeadlineExceeded => None,
+            DatabaseError::Cancelled => None,
+            DatabaseError::CounterError(e) => Some(e),
+            DatabaseError::NotFound => None,
+            DatabaseError::InvalidJson(e) => Some(e),
+            DatabaseError::PatchFailed(e) => Some(e),
+            DatabaseError::ValueTooLarge { .. } => None,
+            DatabaseError::LargeValue => None,
+            DatabaseError::LargeValueChanged => None,
+            DatabaseError::SnapshotNotFound => None,
+            DatabaseError::TooManySnapshots => None,
+            DatabaseError::PreconditionFailed { .. } => None,
+            DatabaseError::IdempotencyKeyReused => None,
         }
     }
 }
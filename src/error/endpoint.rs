@@ -1,27 +1,221 @@
-use crate::error::db::DatabaseError;
-use tonic::Status;
+use crate::error::counter::CounterError;
+use crate::error::db::{DatabaseError, PreconditionFailure};
+use crate::model::key_policy::InvalidKeyReason;
+use crate::model::partition_key::PartitionKey;
+use crate::model::permission::Permission;
+use crate::proto::google::rpc;
+use json_patch::{PatchError, PatchErrorKind};
+use prost::Message;
+use std::collections::HashMap;
+use tonic::{Code, Status};
+
+/// Domain of the `google.rpc.ErrorInfo` details attached to errors.
+const ERROR_DOMAIN: &str = "parapluie";
 
 #[derive(Debug)]
 pub enum EndpointError {
     MissingPartitionKey,
     MissingSortKey,
-    InvalidPartitionKey,
-    InvalidSortKey,
+    InvalidPartitionKey(InvalidKeyReason),
+    InvalidSortKey(InvalidKeyReason),
+    InvalidWriteCondition(&'static str),
+    InvalidWriteMode(&'static str),
+    InvalidRange(&'static str),
+    InvalidHistoryRetention(&'static str),
+    MissingPatch,
+    InvalidPatch(serde_json::Error),
+    ValueTooLarge {
+        size: usize,
+        max_size: usize,
+    },
     NotFound,
+    Unauthenticated(&'static str),
+    PermissionDenied {
+        permission: Permission,
+        partition_key: PartitionKey,
+    },
+    RateLimited,
+    /// An error caused by a field of the request, e.g. `set_values[3].sort_key`.
+    AtField {
+        field: String,
+        error: Box<EndpointError>,
+    },
 
     DatabaseError(DatabaseError),
 }
 
+impl EndpointError {
+    /// Attributes the error to a field of the request.
+    pub fn at(self, field: impl Into<String>) -> Self {
+        EndpointError::AtField {
+            field: field.into(),
+            error: Box::new(self),
+        }
+    }
+
+    fn code(&self) -> Code {
+        match self {
+            EndpointError::MissingPartitionKey => Code::InvalidArgument,
+            EndpointError::MissingSortKey => Code::InvalidArgument,
+            EndpointError::InvalidPartitionKey(_) => Code::InvalidArgument,
+            EndpointError::InvalidSortKey(_) => Code::InvalidArgument,
+            EndpointError::InvalidWriteCondition(_) => Code::InvalidArgument,
+            EndpointError::InvalidWriteMode(_) => Code::InvalidArgument,
+            EndpointError::InvalidRange(_) => Code::InvalidArgument,
+            EndpointError::InvalidHistoryRetention(_) => Code::InvalidArgument,
+            EndpointError::MissingPatch => Code::InvalidArgument,
+            EndpointError::InvalidPatch(_) => Code::InvalidArgument,
+            EndpointError::ValueTooLarge { .. } => Code::InvalidArgument,
+            EndpointError::NotFound => Code::NotFound,
+            EndpointError::Unauthenticated(_) => Code::Unauthenticated,
+            EndpointError::PermissionDenied { .. } => Code::PermissionDenied,
+            EndpointError::RateLimited => Code::ResourceExhausted,
+            EndpointError::AtField { error, .. } => error.code(),
+            EndpointError::DatabaseError(e) => match e {
+                DatabaseError::NoRemainingMessageInChannel => Code::Unavailable,
+                DatabaseError::FailedToSendRequest(_) => Code::Unavailable,
+                DatabaseError::SqliteError(_) => Code::Internal,
+                DatabaseError::QueueFull => Code::ResourceExhausted,
+                DatabaseError::QueueWaitExceeded(_) => Code::ResourceExhausted,
+                DatabaseError::DeadlineExceeded => Code::DeadlineExceeded,
+                DatabaseError::Cancelled => Code::Cancelled,
+                DatabaseError::CounterError(CounterError::Overflow) => Code::OutOfRange,
+                DatabaseError::CounterError(CounterError::NotAnInteger) => Code::FailedPrecondition,
+                DatabaseError::NotFound => Code::NotFound,
+                DatabaseError::InvalidJson(_) => Code::InvalidArgument,
+                DatabaseError::PatchFailed(PatchError { kind: PatchErrorKind::TestFailed, .. }) => Code::FailedPrecondition,
+                DatabaseError::PatchFailed(_) => Code::InvalidArgument,
+                DatabaseError::ValueTooLarge { .. } => Code::InvalidArgument,
+                DatabaseError::LargeValue => Code::FailedPrecondition,
+                DatabaseError::LargeValueChanged => Code::Aborted,
+                DatabaseError::SnapshotNotFound => Code::NotFound,
+                DatabaseError::TooManySnapshots => Code::ResourceExhausted,
+                DatabaseError::PreconditionFailed { failure: PreconditionFailure::ItemAlreadyExists, .. } => Code::AlreadyExists,
+                DatabaseError::PreconditionFailed { .. } => Code::FailedPrecondition,
+                DatabaseError::IdempotencyKeyReused => Code::InvalidArgument,
+            },
+        }
+    }
+
+    /// Reason of the `google.rpc.ErrorInfo` details, which clients can program against.
+    fn reason(&self) -> &'static str {
+        match self {
+            EndpointError::MissingPartitionKey => "MISSING_PARTITION_KEY",
+            EndpointError::MissingSortKey => "MISSING_SORT_KEY",
+            EndpointError::InvalidPartitionKey(_) => "INVALID_PARTITION_KEY",
+            EndpointError::InvalidSortKey(_) => "INVALID_SORT_KEY",
+            EndpointError::InvalidWriteCondition(_) => "INVALID_WRITE_CONDITION",
+            EndpointError::InvalidWriteMode(_) => "INVALID_WRITE_MODE",
+            EndpointError::InvalidRange(_) => "INVALID_RANGE",
+            EndpointError::InvalidHistoryRetention(_) => "INVALID_HISTORY_RETENTION",
+            EndpointError::MissingPatch => "MISSING_PATCH",
+            EndpointError::InvalidPatch(_) => "INVALID_PATCH",
+            EndpointError::ValueTooLarge { .. } => "VALUE_TOO_LARGE",
+            EndpointError::NotFound => "NOT_FOUND",
+            EndpointError::Unauthenticated(_) => "UNAUTHENTICATED",
+            EndpointError::PermissionDenied { .. } => "PERMISSION_DENIED",
+            EndpointError::RateLimited => "RATE_LIMITED",
+            EndpointError::AtField { error, .. } => error.reason(),
+            EndpointError::DatabaseError(e) => match e {
+                DatabaseError::NoRemainingMessageInChannel => "PROCESSOR_UNAVAILABLE",
+                DatabaseError::FailedToSendRequest(_) => "PROCESSOR_UNAVAILABLE",
+                DatabaseError::SqliteError(_) => "INTERNAL",
+                DatabaseError::QueueFull => "QUEUE_FULL",
+                DatabaseError::QueueWaitExceeded(_) => "QUEUE_WAIT_EXCEEDED",
+                DatabaseError::DeadlineExceeded => "DEADLINE_EXCEEDED",
+                DatabaseError::Cancelled => "CANCELLED",
+                DatabaseError::CounterError(CounterError::Overflow) => "COUNTER_OVERFLOW",
+                DatabaseError::CounterError(CounterError::NotAnInteger) => "NOT_A_COUNTER",
+                DatabaseError::NotFound => "NOT_FOUND",
+                DatabaseError::InvalidJson(_) => "NOT_JSON",
+                DatabaseError::PatchFailed(PatchError { kind: PatchErrorKind::TestFailed, .. }) => "PATCH_TEST_FAILED",
+                DatabaseError::PatchFailed(_) => "PATCH_FAILED",
+                DatabaseError::ValueTooLarge { .. } => "VALUE_TOO_LARGE",
+                DatabaseError::LargeValue => "LARGE_VALUE",
+                DatabaseError::LargeValueChanged => "LARGE_VALUE_CHANGED",
+                DatabaseError::SnapshotNotFound => "SNAPSHOT_NOT_FOUND",
+                DatabaseError::TooManySnapshots => "TOO_MANY_SNAPSHOTS",
+                DatabaseError::PreconditionFailed { failure, .. } => match failure {
+                    PreconditionFailure::ConditionNotMet => "CONDITION_NOT_MET",
+                    PreconditionFailure::ItemAlreadyExists => "ITEM_ALREADY_EXISTS",
+                    PreconditionFailure::ItemDoesNotExist => "ITEM_DOES_NOT_EXIST",
+                    PreconditionFailure::VersionMismatch { .. } => "VERSION_MISMATCH",
+                },
+                DatabaseError::IdempotencyKeyReused => "IDEMPOTENCY_KEY_REUSED",
+            },
+        }
+    }
+
+    fn metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+        match self {
+            EndpointError::PermissionDenied { permission, partition_key } => {
+                metadata.insert("permission".to_string(), permission.to_string());
+                metadata.insert("partition_key".to_string(), partition_key.0.to_string());
+            }
+            EndpointError::ValueTooLarge { max_size, .. } => {
+                metadata.insert("max_size".to_string(), max_size.to_string());
+            }
+            EndpointError::AtField { field, error } => {
+                metadata = error.metadata();
+                metadata.insert("field".to_string(), field.clone());
+            }
+            EndpointError::DatabaseError(DatabaseError::PreconditionFailed { failure: PreconditionFailure::VersionMismatch { expected, actual }, .. }) => {
+                metadata.insert("expected_version".to_string(), expected.to_string());
+                metadata.insert("actual_version".to_string(), actual.to_string());
+            }
+            _ => {}
+        }
+        metadata
+    }
+
+    /// Message of the status, without the field the error is attributed to.
+    fn description(&self) -> String {
+        match self {
+            EndpointError::AtField { error, .. } => error.description(),
+            EndpointError::DatabaseError(e) => e.to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Converts the error to a status carrying `google.rpc.ErrorInfo` details, and `google.rpc.BadRequest`
+/// details naming the offending field for invalid arguments.
 impl From<EndpointError> for Status {
     fn from(error: EndpointError) -> Self {
-        match error {
-            EndpointError::MissingPartitionKey => Status::invalid_argument("missing partition key"),
-            EndpointError::MissingSortKey => Status::invalid_argument("missing sort key"),
-            EndpointError::InvalidPartitionKey => Status::invalid_argument("invalid partition key"),
-            EndpointError::InvalidSortKey => Status::invalid_argument("invalid sort key"),
-            EndpointError::NotFound => Status::not_found("not found"),
-            EndpointError::DatabaseError(e) => Status::internal(e.to_string()),
+        let code = error.code();
+        let message = match &error {
+            EndpointError::AtField { field, error } => format!("{}: {}", field, error.description()),
+            _ => error.description(),
+        };
+
+        let mut details = vec![any("google.rpc.ErrorInfo", &rpc::ErrorInfo {
+            reason: error.reason().to_string(),
+            domain: ERROR_DOMAIN.to_string(),
+            metadata: error.metadata(),
+        })];
+        if let (Code::InvalidArgument, EndpointError::AtField { field, error }) = (code, &error) {
+            details.push(any("google.rpc.BadRequest", &rpc::BadRequest {
+                field_violations: vec![rpc::bad_request::FieldViolation {
+                    field: field.clone(),
+                    description: error.description(),
+                }],
+            }));
         }
+
+        let status = rpc::Status {
+            code: code as i32,
+            message: message.clone(),
+            details,
+        };
+        Status::with_details(code, message, status.encode_to_vec().into())
+    }
+}
+
+fn any<M: Message>(name: &str, message: &M) -> prost_types::Any {
+    prost_types::Any {
+        type_url: format!("type.googleapis.com/{}", name),
+        value: message.encode_to_vec(),
     }
 }
 
@@ -30,10 +224,21 @@ impl std::fmt::Display for EndpointError {
         match self {
             EndpointError::MissingPartitionKey => write!(f, "missing partition key"),
             EndpointError::MissingSortKey => write!(f, "missing sort key"),
-            EndpointError::InvalidPartitionKey => write!(f, "invalid partition key"),
-            EndpointError::InvalidSortKey => write!(f, "invalid sort key"),
+            EndpointError::InvalidPartitionKey(reason) => write!(f, "invalid partition key: {}", reason),
+            EndpointError::InvalidSortKey(reason) => write!(f, "invalid sort key: {}", reason),
+            EndpointError::InvalidWriteCondition(reason) => write!(f, "invalid write condition: {}", reason),
+            EndpointError::InvalidWriteMode(reason) => write!(f, "invalid write mode: {}", reason),
+            EndpointError::InvalidRange(reason) => write!(f, "invalid range: {}", reason),
+            EndpointError::InvalidHistoryRetention(reason) => write!(f, "invalid history retention: {}", reason),
+            EndpointError::MissingPatch => write!(f, "missing patch"),
+            EndpointError::InvalidPatch(e) => write!(f, "invalid patch: {}", e),
+            EndpointError::ValueTooLarge { size, max_size } => write!(f, "value is {} bytes long, the maximum is {}", size, max_size),
+            EndpointError::RateLimited => write!(f, "rate limit exceeded"),
+            EndpointError::AtField { field, error } => write!(f, "{}: {}", field, error),
             EndpointError::DatabaseError(e) => write!(f, "database error: {}", e),
             EndpointError::NotFound => write!(f, "not found"),
+            EndpointError::Unauthenticated(reason) => write!(f, "{}", reason),
+            EndpointError::PermissionDenied { permission, partition_key } => write!(f, "{} permission denied on partition {}", permission, partition_key.0),
         }
     }
 }
@@ -43,10 +248,21 @@ impl std::error::Error for EndpointError {
         match self {
             EndpointError::MissingPartitionKey => None,
             EndpointError::MissingSortKey => None,
-            EndpointError::InvalidPartitionKey => None,
-            EndpointError::InvalidSortKey => None,
+            EndpointError::InvalidPartitionKey(_) => None,
+            EndpointError::InvalidSortKey(_) => None,
+            EndpointError::InvalidWriteCondition(_) => None,
+            EndpointError::InvalidWriteMode(_) => None,
+            EndpointError::InvalidRange(_) => None,
+            EndpointError::InvalidHistoryRetention(_) => None,
+            EndpointError::MissingPatch => None,
+            EndpointError::InvalidPatch(e) => Some(e),
+            EndpointError::ValueTooLarge { .. } => None,
+            EndpointError::AtField { error, .. } => Some(error),
             EndpointError::DatabaseError(e) => Some(e),
             EndpointError::NotFound => None,
+            EndpointError::Unauthenticated(_) => None,
+            EndpointError::PermissionDenied { .. } => None,
+            EndpointError::RateLimited => None,
         }
     }
 }
@@ -56,5 +272,3 @@ impl From<DatabaseError> for EndpointError {
         EndpointError::DatabaseError(error)
     }
 }
-
-
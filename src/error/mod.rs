@@ -1,3 +1,4 @@
 pub mod app;
+pub mod counter;
 pub mod db;
 pub mod endpoint;
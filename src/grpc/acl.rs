@@ -0,0 +1,186 @@
+use crate::error::app::AppError;
+use crate::model::identity::Identity;
+use crate::model::key::Key;
+use crate::model::partition_key::PartitionKey;
+use crate::model::permission::Permission;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Name used in the ACL file for requests without an authenticated identity.
+const ANONYMOUS: &str = "anonymous";
+/// Prefix of the identities of certificates, whose subjects contain spaces.
+const CERTIFICATE_PREFIX: &str = "certificate:";
+
+#[derive(Debug)]
+enum PartitionPattern {
+    Exact(String),
+    Prefix(String),
+}
+
+impl PartitionPattern {
+    /// Patterns are text, so binary partition keys are only matched by `*`.
+    fn matches(&self, partition_key: &PartitionKey) -> bool {
+        match (self, &partition_key.0) {
+            (PartitionPattern::Exact(key), Key::Text(text)) => text == key,
+            (PartitionPattern::Prefix(prefix), Key::Text(text)) => text.starts_with(prefix.as_str()),
+            (PartitionPattern::Exact(_), Key::Bytes(_)) => false,
+            (PartitionPattern::Prefix(prefix), Key::Bytes(_)) => prefix.is_empty(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    permissions: Vec<Permission>,
+    pattern: PartitionPattern,
+}
+
+/// Permissions of each identity on the partitions.
+///
+/// Every request is allowed when no ACL file is configured.
+#[derive(Debug, Default)]
+pub struct Acl {
+    rules_by_identity: Option<HashMap<String, Vec<Rule>>>,
+}
+
+impl Acl {
+    /// Loads an ACL file, where each line is `<permissions> <partition pattern> <identity>`:
+    /// - permissions are a comma-separated list of `read`, `write`, `delete` and `admin`,
+    /// - a pattern ending with `*` matches every partition key starting with what precedes it, any
+    ///   other pattern only matches the exact partition key; binary partition keys are only matched by
+    ///   `*`,
+    /// - the identity is `token:<name>`, `certificate:<subject>` or `anonymous`.
+    ///
+    /// Fields are separated by spaces or tabs. Empty lines and lines starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content, path)
+    }
+
+    /// Parses the content of an ACL file, named by `path` in errors.
+    fn parse(content: &str, path: &Path) -> Result<Self, AppError> {
+        let mut rules_by_identity: HashMap<String, Vec<Rule>> = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |reason: String| AppError::InvalidConfiguration(
+                format!("{}:{}: {}", path.display(), index + 1, reason)
+            );
+
+            // NOTE: The identity is the rest of the line, since the subject of a certificate has spaces.
+            let mut fields = line.split_whitespace();
+            let (Some(permissions), Some(pattern)) = (fields.next(), fields.next()) else {
+                return Err(invalid("expected <permissions> <partition pattern> <identity>".to_string()));
+            };
+            let identity = fields.collect::<Vec<_>>().join(" ");
+            if identity.is_empty() {
+                return Err(invalid("expected <permissions> <partition pattern> <identity>".to_string()));
+            }
+            if identity.contains(' ') && !identity.starts_with(CERTIFICATE_PREFIX) {
+                return Err(invalid(format!("unexpected field after the identity in {:?}", identity)));
+            }
+
+            let permissions = permissions.split(',')
+                .map(|permission| Permission::try_from(permission)
+                    .map_err(|_| invalid(format!("unknown permission {:?}", permission))))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let pattern = match pattern.strip_suffix('*') {
+                Some(prefix) => PartitionPattern::Prefix(prefix.to_string()),
+                None => PartitionPattern::Exact(pattern.to_string()),
+            };
+
+            rules_by_identity
+                .entry(identity)
+                .or_default()
+                .push(Rule { permissions, pattern });
+        }
+
+        Ok(Self { rules_by_identity: Some(rules_by_identity) })
+    }
+
+    pub fn is_allowed(&self, identity: Option<&Identity>, permission: Permission, partition_key: &PartitionKey) -> bool {
+        let Some(rules_by_identity) = &self.rules_by_identity else {
+            return true;
+        };
+
+        let identity = identity
+            .map(|identity| identity.to_string())
+            .unwrap_or_else(|| ANONYMOUS.to_string());
+
+        rules_by_identity
+            .get(&identity)
+            .into_iter()
+            .flatten()
+            .any(|rule| {
+                rule.pattern.matches(partition_key)
+                    && rule.permissions.iter().any(|granted| granted.grants(permission))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Acl, AppError> {
+        Acl::parse(content, Path::new("acl"))
+    }
+
+    fn partition_key(text: &str) -> PartitionKey {
+        PartitionKey(Key::Text(text.to_string()))
+    }
+
+    fn token(name: &str) -> Identity {
+        Identity::Token { name: name.to_string() }
+    }
+
+    #[test]
+    fn comments_blank_lines_and_runs_of_whitespace_are_ignored() {
+        let acl = parse("# Orders\n\n   \nread,write \t  orders/*\t\ttoken:alice  \n").unwrap();
+
+        assert!(acl.is_allowed(Some(&token("alice")), Permission::Write, &partition_key("orders/1")));
+        assert!(!acl.is_allowed(Some(&token("alice")), Permission::Delete, &partition_key("orders/1")));
+    }
+
+    #[test]
+    fn certificate_identities_keep_the_spaces_of_their_subject() {
+        let acl = parse("admin  *  certificate:CN=ops,   O=Parapluie").unwrap();
+        let identity = Identity::Certificate {
+            subject: "CN=ops, O=Parapluie".to_string(),
+            subject_alt_names: Vec::new(),
+        };
+
+        assert!(acl.is_allowed(Some(&identity), Permission::Delete, &partition_key("orders")));
+    }
+
+    #[test]
+    fn lines_with_missing_fields_extra_fields_or_unknown_permissions_are_rejected() {
+        for line in ["read", "read orders/*", "read,write  orders/*  token:alice extra", "read,own orders token:alice"] {
+            assert!(matches!(parse(line), Err(AppError::InvalidConfiguration(_))), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn patterns_match_exact_keys_and_prefixes() {
+        let acl = parse("read orders token:alice\nread users/* token:alice\nread * anonymous").unwrap();
+        let alice = Some(token("alice"));
+
+        assert!(acl.is_allowed(alice.as_ref(), Permission::Read, &partition_key("orders")));
+        assert!(!acl.is_allowed(alice.as_ref(), Permission::Read, &partition_key("orders/1")));
+        assert!(acl.is_allowed(alice.as_ref(), Permission::Read, &partition_key("users/1")));
+        assert!(acl.is_allowed(alice.as_ref(), Permission::Read, &partition_key("users/")));
+        assert!(!acl.is_allowed(alice.as_ref(), Permission::Read, &partition_key("users")));
+        assert!(!acl.is_allowed(alice.as_ref(), Permission::Read, &PartitionKey(Key::Bytes(b"users/1".to_vec()))));
+        assert!(!acl.is_allowed(Some(&token("bob")), Permission::Read, &partition_key("orders")));
+        assert!(acl.is_allowed(None, Permission::Read, &PartitionKey(Key::Bytes(vec![0xff]))));
+    }
+
+    #[test]
+    fn every_request_is_allowed_without_an_acl_file() {
+        assert!(Acl::default().is_allowed(None, Permission::Admin, &partition_key("orders")));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::error::app::AppError;
+use crate::error::endpoint::EndpointError;
+use crate::grpc::watch;
+use crate::metrics::Metrics;
+use crate::model::identity::Identity;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+use tracing::{info, warn};
+
+const AUTHORIZATION_HEADER: &str = "authorization";
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Tokens allowed to call the service, indexed by the hex-encoded SHA-256 of the token.
+#[derive(Debug, Default)]
+pub struct TokenStore {
+    names_by_hash: HashMap<String, String>,
+}
+
+impl TokenStore {
+    /// Loads a token file, where each line is `<name>:<hex-encoded SHA-256 of the token>`. Empty
+    /// lines and lines starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let content = std::fs::read_to_string(path)?;
+
+        let mut names_by_hash = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |reason: &str| AppError::InvalidConfiguration(
+                format!("{}:{}: {}", path.display(), index + 1, reason)
+            );
+
+            let (name, hash) = line.split_once(':')
+                .ok_or_else(|| invalid("expected <name>:<sha256>"))?;
+            let (name, hash) = (name.trim(), hash.trim().to_ascii_lowercase());
+
+            if name.is_empty() {
+                return Err(invalid("empty token name"));
+            }
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid("expected a hex-encoded SHA-256 hash"));
+            }
+            names_by_hash.insert(hash, name.to_string());
+        }
+
+        Ok(Self { names_by_hash })
+    }
+
+    /// Identifies the caller by the bearer token of the request.
+    fn authenticate_request(&self, request: &Request<()>) -> Result<Identity, EndpointError> {
+        let token = request.metadata()
+            .get(AUTHORIZATION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix(BEARER_PREFIX))
+            .ok_or(EndpointError::Unauthenticated("missing bearer token"))?;
+
+        self.authenticate(token)
+            .ok_or(EndpointError::Unauthenticated("invalid bearer token"))
+    }
+
+    fn authenticate(&self, token: &str) -> Option<Identity> {
+        let hash = hex(&Sha256::digest(token.as_bytes()));
+        self.names_by_hash
+            .get(&hash)
+            .map(|name| Identity::Token { name: name.clone() })
+    }
+}
+
+/// Rejects requests that do not carry a known bearer token, and records the identity of the caller in
+/// the request extensions.
+///
+/// Every request is accepted when no token store is configured.
+///
+/// NOTE: Interceptors do not know the method of the request, so rejected requests are counted apart from
+/// the requests observed by the service.
+#[derive(Clone, Debug, Default)]
+pub struct AuthInterceptor {
+    tokens: Option<(Arc<RwLock<TokenStore>>, Metrics)>,
+}
+
+impl AuthInterceptor {
+    pub fn new(tokens: Arc<RwLock<TokenStore>>, metrics: Metrics) -> Self {
+        Self { tokens: Some((tokens, metrics)) }
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let Some((tokens, metrics)) = &self.tokens else {
+            return Ok(request);
+        };
+
+        let identity = tokens.read()
+            .map_err(|_| Status::internal("token store is unavailable"))?
+            .authenticate_request(&request)
+            .inspect_err(|_| metrics.request_unauthenticated())?;
+
+        request.extensions_mut().insert(identity);
+        Ok(request)
+    }
+}
+
+
+/// Reloads the token store whenever the token file changes on disk. Invalid files are ignored, and the
+/// previous tokens are kept.
+pub async fn watch_token_file(path: PathBuf, tokens: Arc<RwLock<TokenStore>>) {
+    let paths = [path.as_path()];
+    let mut loaded = watch::modification_times(&paths);
+
+    loop {
+        loaded = watch::wait_for_modification(&paths, &loaded).await;
+
+        match TokenStore::load(&path) {
+            Ok(store) => {
+                info!(count = store.names_by_hash.len(), "reloaded the token file");
+                if let Ok(mut tokens) = tokens.write() {
+                    *tokens = store;
+                }
+            }
+            Err(e) => warn!(error = %e, "ignoring invalid token file"),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    const TOKEN: &str = "secret";
+
+    fn interceptor(metrics: &Metrics) -> AuthInterceptor {
+        let names_by_hash = HashMap::from([(hex(&Sha256::digest(TOKEN.as_bytes())), "alice".to_string())]);
+        AuthInterceptor::new(Arc::new(RwLock::new(TokenStore { names_by_hash })), metrics.clone())
+    }
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(authorization) = authorization {
+            request.metadata_mut().insert(AUTHORIZATION_HEADER, authorization.parse().unwrap());
+        }
+        request
+    }
+
+    fn unauthenticated_requests(metrics: &Metrics) -> String {
+        metrics.encode()
+            .unwrap()
+            .lines()
+            .find(|line| line.starts_with("parapluie_grpc_unauthenticated_requests_total "))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn known_tokens_identify_the_caller() {
+        let metrics = Metrics::new(std::env::temp_dir().join("parapluie-unused.sqlite")).unwrap();
+
+        let request = interceptor(&metrics).call(request(Some("Bearer secret"))).unwrap();
+
+        assert_eq!(request.extensions().get::<Identity>(), Some(&Identity::Token { name: "alice".to_string() }));
+        assert_eq!(unauthenticated_requests(&metrics), "parapluie_grpc_unauthenticated_requests_total 0");
+    }
+
+    #[test]
+    fn missing_malformed_and_unknown_tokens_are_rejected_and_counted() {
+        let metrics = Metrics::new(std::env::temp_dir().join("parapluie-unused.sqlite")).unwrap();
+        let mut interceptor = interceptor(&metrics);
+
+        for authorization in [None, Some("secret"), Some("Basic secret"), Some("Bearer other")] {
+            let status = interceptor.call(request(authorization)).unwrap_err();
+            assert_eq!(status.code(), Code::Unauthenticated, "{:?}", authorization);
+        }
+        assert_eq!(unauthenticated_requests(&metrics), "parapluie_grpc_unauthenticated_requests_total 4");
+    }
+
+    #[test]
+    fn every_request_is_accepted_without_a_token_store() {
+        let request = AuthInterceptor::default().call(request(None)).unwrap();
+
+        assert_eq!(request.extensions().get::<Identity>(), None);
+    }
+}
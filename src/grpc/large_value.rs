@@ -0,0 +1,119 @@
+use crate::error::db::DatabaseError;
+use crate::model::large_item::UploadId;
+use crate::model::partition_key::PartitionKey;
+use crate::model::request_context::RequestContext;
+use crate::model::sort_key::SortKey;
+use crate::model::write_condition::WriteCondition;
+use crate::repository::Repository;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tonic::Status;
+use tracing::{warn, Instrument, Span};
+
+/// Bounds the bytes of the chunks of large values held in memory by all the streams of `PutLarge` and
+/// `GetLarge`, between the network and the processor.
+#[derive(Clone, Debug)]
+pub struct LargeValueBuffer {
+    semaphore: Arc<Semaphore>,
+    size: usize,
+}
+
+impl LargeValueBuffer {
+    pub fn new(size: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(size)),
+            size,
+        }
+    }
+
+    /// Waits until a chunk of this size fits in the buffer, and holds its room until the permit is
+    /// dropped. A chunk larger than the buffer waits until the buffer is empty.
+    pub async fn reserve(&self, size: usize) -> Result<OwnedSemaphorePermit, Status> {
+        let permits = size.min(self.size).min(u32::MAX as usize) as u32;
+        self.semaphore
+            .clone()
+            .acquire_many_owned(permits)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+/// The chunks of a `PutLarge` received so far, which are dropped unless they replace the value of the item.
+pub struct LargeUpload {
+    repository: Repository,
+    context: RequestContext,
+    upload_id: UploadId,
+    chunk_count: u64,
+    finished: bool,
+}
+
+impl LargeUpload {
+    pub fn new(repository: Repository, context: RequestContext) -> Self {
+        Self {
+            repository,
+            context,
+            upload_id: UploadId::generate(),
+            chunk_count: 0,
+            finished: false,
+        }
+    }
+
+    pub async fn append(&mut self, chunk: Vec<u8>) -> Result<(), DatabaseError> {
+        // NOTE: The chunk is counted before it is sent, so that it is dropped even if the RPC is cancelled
+        // while the processor writes it.
+        let chunk_index = self.chunk_count;
+        self.chunk_count += 1;
+        self.repository.append_large_chunk(self.context.clone(), self.upload_id.clone(), chunk_index, chunk).await
+    }
+
+    /// Replaces the item with a value made of the chunks, and returns its new version.
+    pub async fn finish(mut self, partition_key: PartitionKey, sort_key: SortKey, sort_key_is_tuple: bool, write_condition: WriteCondition) -> Result<u64, DatabaseError> {
+        let version = self.repository.put_large(self.context.clone(), partition_key, sort_key, sort_key_is_tuple, write_condition, self.upload_id.clone()).await?;
+        self.finished = true;
+        Ok(version)
+    }
+}
+
+impl Drop for LargeUpload {
+    /// Drops the chunks of an upload that failed or whose RPC was cancelled.
+    ///
+    /// NOTE: This runs when the future of the RPC is dropped, so the chunks are dropped by a task of its
+    /// own, without the deadline of the RPC, which may be over.
+    fn drop(&mut self) {
+        if self.finished || self.chunk_count == 0 {
+            return;
+        }
+
+        let repository = self.repository.clone();
+        let context = RequestContext { deadline: None, ..self.context.clone() };
+        let upload_id = self.upload_id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = repository.abort_large_upload(context, upload_id).await {
+                warn!(error = %e, "failed to drop the chunks of an unfinished upload");
+            }
+        }.instrument(Span::current()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn chunks_wait_until_they_fit_in_the_buffer() {
+        let buffer = LargeValueBuffer::new(10);
+
+        let first = buffer.reserve(6).await.unwrap();
+        assert!(timeout(Duration::from_millis(10), buffer.reserve(6)).await.is_err());
+        drop(first);
+        let second = buffer.reserve(6).await.unwrap();
+        drop(second);
+
+        // NOTE: A chunk larger than the buffer takes all of it, rather than waiting forever.
+        let larger = buffer.reserve(100).await.unwrap();
+        assert!(timeout(Duration::from_millis(10), buffer.reserve(1)).await.is_err());
+        drop(larger);
+    }
+}
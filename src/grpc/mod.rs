@@ -1,3 +1,14 @@
+mod acl;
+mod auth;
+mod large_value;
+mod rate_limit;
+mod server;
 mod service;
+mod tls;
+mod watch;
 
+pub use acl::Acl;
+pub use auth::{watch_token_file, AuthInterceptor, TokenStore};
+pub use rate_limit::RateLimiter;
+pub use server::serve;
 pub use service::Service;
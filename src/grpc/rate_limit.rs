@@ -0,0 +1,121 @@
+use crate::config::RateLimitConfig;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    /// Refills the bucket for the time elapsed since it was last refilled.
+    fn refill(&mut self, config: RateLimitConfig, now: Instant) {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.rate).min(config.burst);
+        self.refilled_at = now;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_client: HashMap<(String, &'static str), Bucket>,
+    /// `None` until the first request.
+    swept_at: Option<Instant>,
+}
+
+/// Token buckets limiting the rate of requests of each client to each RPC.
+///
+/// Every request is allowed when no rate limit is configured.
+///
+/// NOTE: Unauthenticated clients are identified by their address, so a client rotating addresses
+/// creates a bucket for each of them. Buckets that refilled completely are therefore dropped
+/// periodically, which is lossless as a new bucket starts full.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    config: Option<RateLimitConfig>,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: Option<RateLimitConfig>) -> Self {
+        Self {
+            config,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Takes a token from the bucket of the client for the method, if there is one left.
+    pub fn try_acquire(&self, client: String, method: &'static str) -> bool {
+        self.try_acquire_at(client, method, Instant::now())
+    }
+
+    fn try_acquire_at(&self, client: String, method: &'static str, now: Instant) -> bool {
+        let Some(config) = self.config else {
+            return true;
+        };
+        let Ok(mut buckets) = self.buckets.lock() else {
+            return true;
+        };
+
+        match buckets.swept_at {
+            Some(swept_at) if now.duration_since(swept_at) < SWEEP_INTERVAL => {}
+            Some(_) => {
+                buckets.by_client.retain(|_, bucket| {
+                    bucket.refill(config, now);
+                    bucket.tokens < config.burst
+                });
+                buckets.swept_at = Some(now);
+            }
+            None => buckets.swept_at = Some(now),
+        }
+
+        let bucket = buckets.by_client
+            .entry((client, method))
+            .or_insert(Bucket { tokens: config.burst, refilled_at: now });
+        bucket.refill(config, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: RateLimitConfig = RateLimitConfig { rate: 1.0, burst: 2.0 };
+
+    #[test]
+    fn refilled_buckets_are_evicted() {
+        let limiter = RateLimiter::new(Some(CONFIG));
+        let start = Instant::now();
+
+        for client in 0..100 {
+            assert!(limiter.try_acquire_at(client.to_string(), "Get", start));
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().by_client.len(), 100);
+
+        assert!(limiter.try_acquire_at("other".to_string(), "Get", start + SWEEP_INTERVAL));
+        assert_eq!(limiter.buckets.lock().unwrap().by_client.len(), 1);
+    }
+
+    #[test]
+    fn depleted_buckets_are_kept() {
+        let limiter = RateLimiter::new(Some(RateLimitConfig { rate: 0.001, burst: 2.0 }));
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at("client".to_string(), "Get", start));
+        assert!(limiter.try_acquire_at("client".to_string(), "Get", start));
+
+        assert!(!limiter.try_acquire_at("client".to_string(), "Get", start + SWEEP_INTERVAL));
+        assert_eq!(limiter.buckets.lock().unwrap().by_client.len(), 1);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::config::TlsConfig;
+use crate::error::app::AppError;
+use crate::grpc::tls;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::select;
+use tokio::sync::oneshot;
+use tokio_stream::Stream;
+use tonic::service::Routes;
+use tonic::transport::Server;
+use tracing::{info, warn};
+
+/// Serves the routes over plaintext, or over TLS when configured.
+///
+/// NOTE: tonic cannot swap the certificates of a running server, so a new server is started with the
+/// new certificates whenever they change on disk. It accepts on the same listener, which stays bound,
+/// while the previous server drains its connections in the background.
+pub async fn serve(addr: SocketAddr, tls: Option<TlsConfig>, routes: Routes) -> Result<(), AppError> {
+    let Some(tls) = tls else {
+        Server::builder()
+            .add_routes(routes)
+            .serve(addr)
+            .await?;
+        return Ok(());
+    };
+
+    let listener = Arc::new(TcpListener::bind(addr).await?);
+    let mut tls_config = tls::load(&tls)?;
+
+    loop {
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = Server::builder()
+            .tls_config(tls_config)?
+            .add_routes(routes.clone())
+            .serve_with_incoming_shutdown(SharedIncoming(listener.clone()), async {
+                let _ = stopped.await;
+            });
+        let mut server = tokio::spawn(server);
+
+        // NOTE: Certificates that fail to load are ignored by `wait_for_change`, so that the server keeps
+        // serving with the previous ones.
+        tls_config = select! {
+            result = &mut server => {
+                result??;
+                return Ok(());
+            }
+            tls_config = tls::wait_for_change(&tls) => tls_config,
+        };
+
+        info!("starting a new gRPC server to reload the TLS certificates");
+        let _ = stop.send(());
+        tokio::spawn(async move {
+            match server.await {
+                Ok(Ok(())) => info!("previous gRPC server drained its connections"),
+                Ok(Err(e)) => warn!(error = %e, "previous gRPC server failed while draining its connections"),
+                Err(e) => warn!(error = %e, "previous gRPC server panicked while draining its connections"),
+            }
+        });
+    }
+}
+
+/// Accepts the connections of a listener shared by the successive servers.
+struct SharedIncoming(Arc<TcpListener>);
+
+impl Stream for SharedIncoming {
+    type Item = std::io::Result<TcpStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_accept(cx)
+            .map(|result| Some(result.map(|(stream, _)| stream)))
+    }
+}
@@ -1,54 +1,233 @@
+use crate::config::{DeleteLimits, ValueLimits};
+use crate::error::db::{DatabaseError, PreconditionFailure};
 use crate::error::endpoint::EndpointError;
-use crate::error::endpoint::EndpointError::{InvalidPartitionKey, InvalidSortKey, MissingPartitionKey, MissingSortKey};
+use crate::grpc::acl::Acl;
+use crate::grpc::large_value::{LargeUpload, LargeValueBuffer};
+use crate::grpc::rate_limit::RateLimiter;
+use crate::grpc::tls;
+use crate::metrics::Metrics;
+use crate::error::endpoint::EndpointError::{InvalidHistoryRetention, InvalidPartitionKey, InvalidSortKey, InvalidRange, InvalidWriteCondition, InvalidWriteMode, MissingPartitionKey, MissingPatch, MissingSortKey, ValueTooLarge};
+use crate::model::identity::Identity;
+use crate::model::history_retention::HistoryRetention;
+use crate::model::idempotency_key::IdempotencyKey;
+use crate::model::item::Item;
+use crate::model::get_options::GetOptions;
+use crate::model::key::Key;
+use crate::model::key_policy::{InvalidKeyReason, KeyPolicy};
+use crate::model::large_item::LargeItem;
+use crate::model::list_options::ListOptions;
+use crate::model::partition::Partition;
 use crate::model::partition_key::PartitionKey;
+use crate::model::patch::Patch;
+use crate::model::permission::Permission;
+use crate::model::projection::Projection;
+use crate::model::request_context::{RequestContext, RequestId};
 use crate::model::set_value::SetValue;
+use crate::model::snapshot::SnapshotId;
 use crate::model::sort_key::SortKey;
-use crate::model::write_condition::WriteCondition;
+use crate::model::sort_key_range::SortKeyRange;
+use crate::model::tuple;
+use crate::model::tuple::TupleElement;
+use crate::model::write_condition::{Existence, WriteCondition};
+use crate::model::write_mode::WriteMode;
 use crate::proto::parapluie as proto;
 use crate::proto::parapluie::parapluie_db_server::ParapluieDb;
 use crate::repository::Repository;
+use crate::telemetry;
+use prost::Message;
+use sha2::{Digest, Sha256};
 use std::collections::Bound;
+use std::future::Future;
 use std::ops::Bound::{Excluded, Included, Unbounded};
-use std::time::SystemTime;
-use tonic::{Request, Response, Status};
+use std::time::{Duration, Instant, SystemTime};
+use time::OffsetDateTime;
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Request, Response, Status, Streaming};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{field, info, info_span, Instrument, Span};
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const TIMEOUT_HEADER: &str = "grpc-timeout";
+
+type GetLargeStream = ReceiverStream<Result<proto::GetLargeResponse, Status>>;
 
 #[derive(Debug)]
 pub struct Service {
     repository: Repository,
+    metrics: Metrics,
+    acl: Acl,
+    rate_limiter: RateLimiter,
+    key_policy: KeyPolicy,
+    value_limits: ValueLimits,
+    delete_limits: DeleteLimits,
+    large_value_buffer: LargeValueBuffer,
 }
 
 impl Service {
-    pub fn new(repository: Repository) -> Self {
+    pub fn new(repository: Repository, metrics: Metrics, acl: Acl, rate_limiter: RateLimiter, key_policy: KeyPolicy, value_limits: ValueLimits, delete_limits: DeleteLimits) -> Self {
         Self {
-            repository
+            repository,
+            metrics,
+            acl,
+            rate_limiter,
+            key_policy,
+            value_limits,
+            delete_limits,
+            large_value_buffer: LargeValueBuffer::new(value_limits.large_value_buffer_size),
+        }
+    }
+
+    fn authorize(&self, context: &RequestContext, permission: Permission, partition_key: &PartitionKey) -> Result<(), EndpointError> {
+        if self.acl.is_allowed(context.identity.as_ref(), permission, partition_key) {
+            Ok(())
+        } else {
+            Err(EndpointError::PermissionDenied {
+                permission,
+                partition_key: partition_key.clone(),
+            })
+        }
+    }
+
+    /// Runs an RPC handler inside a span carrying the request ID, and records its status code and
+    /// latency. Requests over the rate limit of the client are rejected without running the handler.
+    async fn observe<Req, Res, F, Fut>(&self, method: &'static str, request: Request<Req>, handler: F) -> Result<Response<Res>, Status>
+    where
+        F: FnOnce(RequestContext, Request<Req>) -> Fut,
+        Fut: Future<Output=Result<Response<Res>, Status>>,
+    {
+        let context = request_context(&request);
+        let client = client_name(&context, &request);
+        let request_id = context.request_id.clone();
+        let span = info_span!("rpc", method, request_id = %request_id, identity = field::Empty);
+        if let Some(identity) = &context.identity {
+            span.record("identity", field::display(identity));
+        }
+        telemetry::set_remote_parent(&span, request.metadata());
+
+        async move {
+            let start = Instant::now();
+            let mut result = if self.rate_limiter.try_acquire(client, method) {
+                handler(context, request).await
+            } else {
+                Err(EndpointError::RateLimited.into())
+            };
+
+            let code = match &result {
+                Ok(_) => Code::Ok,
+                Err(status) => status.code(),
+            };
+            self.metrics.observe_request(method, code, start.elapsed());
+            info!(?code, "request completed");
+
+            if let (Ok(response), Ok(value)) = (&mut result, MetadataValue::try_from(&request_id.0)) {
+                response.metadata_mut().insert(REQUEST_ID_HEADER, value);
+            }
+            result
         }
+            .instrument(span)
+            .await
     }
 }
 
 #[tonic::async_trait]
 impl ParapluieDb for Service {
     async fn set(&self, request: Request<proto::SetRequest>) -> Result<Response<proto::SetResponse>, Status> {
+        self.observe("Set", request, |context, request| self.handle_set(context, request)).await
+    }
+
+    async fn get(&self, request: Request<proto::GetRequest>) -> Result<Response<proto::GetResponse>, Status> {
+        self.observe("Get", request, |context, request| self.handle_get(context, request)).await
+    }
+
+    async fn list(&self, request: Request<proto::ListRequest>) -> Result<Response<proto::ListResponse>, Status> {
+        self.observe("List", request, |context, request| self.handle_list(context, request)).await
+    }
+
+    async fn count(&self, request: Request<proto::CountRequest>) -> Result<Response<proto::CountResponse>, Status> {
+        self.observe("Count", request, |context, request| self.handle_count(context, request)).await
+    }
+
+    async fn delete_range(&self, request: Request<proto::DeleteRangeRequest>) -> Result<Response<proto::DeleteRangeResponse>, Status> {
+        self.observe("DeleteRange", request, |context, request| self.handle_delete_range(context, request)).await
+    }
+
+    async fn undelete(&self, request: Request<proto::UndeleteRequest>) -> Result<Response<proto::UndeleteResponse>, Status> {
+        self.observe("Undelete", request, |context, request| self.handle_undelete(context, request)).await
+    }
+
+    async fn get_history(&self, request: Request<proto::GetHistoryRequest>) -> Result<Response<proto::GetHistoryResponse>, Status> {
+        self.observe("GetHistory", request, |context, request| self.handle_get_history(context, request)).await
+    }
+
+    async fn set_history_retention(&self, request: Request<proto::SetHistoryRetentionRequest>) -> Result<Response<proto::SetHistoryRetentionResponse>, Status> {
+        self.observe("SetHistoryRetention", request, |context, request| self.handle_set_history_retention(context, request)).await
+    }
+
+    async fn begin_snapshot(&self, request: Request<proto::BeginSnapshotRequest>) -> Result<Response<proto::BeginSnapshotResponse>, Status> {
+        self.observe("BeginSnapshot", request, |context, request| self.handle_begin_snapshot(context, request)).await
+    }
+
+    async fn end_snapshot(&self, request: Request<proto::EndSnapshotRequest>) -> Result<Response<proto::EndSnapshotResponse>, Status> {
+        self.observe("EndSnapshot", request, |context, request| self.handle_end_snapshot(context, request)).await
+    }
+
+    async fn list_partitions(&self, request: Request<proto::ListPartitionsRequest>) -> Result<Response<proto::ListPartitionsResponse>, Status> {
+        self.observe("ListPartitions", request, |context, request| self.handle_list_partitions(context, request)).await
+    }
+
+    async fn increment(&self, request: Request<proto::IncrementRequest>) -> Result<Response<proto::IncrementResponse>, Status> {
+        self.observe("Increment", request, |context, request| self.handle_increment(context, request)).await
+    }
+
+    async fn update(&self, request: Request<proto::UpdateRequest>) -> Result<Response<proto::UpdateResponse>, Status> {
+        self.observe("Update", request, |context, request| self.handle_update(context, request)).await
+    }
+
+    async fn put_large(&self, request: Request<Streaming<proto::PutLargeRequest>>) -> Result<Response<proto::PutLargeResponse>, Status> {
+        self.observe("PutLarge", request, |context, request| self.handle_put_large(context, request)).await
+    }
+
+    type GetLargeStream = GetLargeStream;
+
+    async fn get_large(&self, request: Request<proto::GetLargeRequest>) -> Result<Response<Self::GetLargeStream>, Status> {
+        self.observe("GetLarge", request, |context, request| self.handle_get_large(context, request)).await
+    }
+}
+
+impl Service {
+    async fn handle_set(&self, context: RequestContext, request: Request<proto::SetRequest>) -> Result<Response<proto::SetResponse>, Status> {
         let request: proto::SetRequest = request.into_inner();
+        // NOTE: The request is hashed whole, key included, so that a retry has the same hash, while the
+        // same key sent with different values does not.
+        let idempotency_key = Some(request.idempotency_key.clone())
+            .filter(|key| !key.is_empty())
+            .map(|key| IdempotencyKey { key, request_hash: Sha256::digest(request.encode_to_vec()).to_vec() });
 
-        let partition_key: PartitionKey = convert_partition_key(request.partition_key)?;
+        let partition_key: PartitionKey = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Write, &partition_key)?;
         let set_values = request.set_values
             .into_iter()
-            .map(|set_value| {
-                let sort_key = convert_sort_key(set_value.sort_key)?;
-
-                let version_equals = set_value.write_condition
-                    .map(|write_condition| write_condition.version_equals)
-                    .unwrap_or(None);
-
+            .enumerate()
+            .map(|(index, set_value)| {
+                let field = |name| format!("set_values[{}].{}", index, name);
+                let sort_key_is_tuple = is_tuple_sort_key(&set_value.sort_key);
+                let sort_key = convert_sort_key(&self.key_policy, set_value.sort_key)
+                    .map_err(|e| e.at(field("sort_key")))?;
+                let write_condition = convert_write_condition(set_value.write_condition)
+                    .map_err(|e| e.at(field("write_condition")))?;
+                let write_mode = convert_write_mode(set_value.write_mode, &write_condition)
+                    .map_err(|e| e.at(field("write_mode")))?;
+                check_value_size(set_value.value.len(), self.value_limits.max_value_size)
+                    .map_err(|e| e.at(field("value")))?;
                 let value = set_value.value;
 
-                let write_condition = WriteCondition {
-                    version_equals,
-                };
-
                 let set_value = SetValue {
                     sort_key,
+                    sort_key_is_tuple,
                     write_condition,
+                    write_mode,
                     value,
                 };
 
@@ -56,116 +235,743 @@ impl ParapluieDb for Service {
             })
             .collect::<Result<Vec<_>, EndpointError>>()?;
 
-        let result = self.repository.set(partition_key, set_values)
+        self.repository.set(context, partition_key, set_values, idempotency_key)
             .await
-            .map_err(EndpointError::DatabaseError)?;
+            .map_err(write_error)?;
 
         Ok(Response::new(proto::SetResponse {
-            updated: result,
+            updated: true,
         }))
     }
 
-    async fn get(&self, request: Request<proto::GetRequest>) -> Result<Response<proto::GetResponse>, Status> {
+    async fn handle_get(&self, context: RequestContext, request: Request<proto::GetRequest>) -> Result<Response<proto::GetResponse>, Status> {
         let request: proto::GetRequest = request.into_inner();
 
-        let partition_key = convert_partition_key(request.partition_key)?;
-        let sort_key = convert_sort_key(request.sort_key)?;
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Read, &partition_key)?;
+        let sort_key = convert_sort_key(&self.key_policy, request.sort_key)
+            .map_err(|e| e.at("sort_key"))?;
 
-        let result = self.repository.get(partition_key, sort_key)
+        let options = GetOptions {
+            projection: convert_projection(request.keys_only),
+            include_deleted: request.include_deleted,
+            version: Some(request.version).filter(|version| *version > 0),
+            snapshot_id: convert_snapshot_id(request.snapshot_id),
+        };
+        let result = self.repository.get(context, partition_key, sort_key, options)
             .await
             .map_err(EndpointError::DatabaseError)?
             .ok_or(EndpointError::NotFound)?;
 
-        let created_at: SystemTime = result.created_at.into();
-        let updated_at: SystemTime = result.updated_at.into();
+        Ok(Response::new(proto::GetResponse {
+            item: Some(convert_item(result)),
+        }))
+    }
 
-        let item = proto::Item {
-            partition_key: Some(proto::PartitionKey {
-                value: result.partition_key.0,
-            }),
-            sort_key: Some(proto::SortKey {
-                value: result.sort_key.0,
-            }),
-            value: result.value,
-            created_at: Some(created_at.into()),
-            updated_at: Some(updated_at.into()),
-            version: result.version,
-        };
+    async fn handle_increment(&self, context: RequestContext, request: Request<proto::IncrementRequest>) -> Result<Response<proto::IncrementResponse>, Status> {
+        let request: proto::IncrementRequest = request.into_inner();
 
-        Ok(Response::new(proto::GetResponse {
-            item: Some(item),
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Write, &partition_key)?;
+        let sort_key_is_tuple = is_tuple_sort_key(&request.sort_key);
+        let sort_key = convert_sort_key(&self.key_policy, request.sort_key)
+            .map_err(|e| e.at("sort_key"))?;
+
+        let counter = self.repository.increment(context, partition_key, sort_key, sort_key_is_tuple, request.delta, request.initial_value)
+            .await
+            .map_err(EndpointError::DatabaseError)?;
+
+        Ok(Response::new(proto::IncrementResponse {
+            value: counter.value,
+            version: counter.version,
         }))
     }
 
-    async fn list(&self, request: Request<proto::ListRequest>) -> Result<Response<proto::ListResponse>, Status> {
-        let request: proto::ListRequest = request.into_inner();
+    async fn handle_update(&self, context: RequestContext, request: Request<proto::UpdateRequest>) -> Result<Response<proto::UpdateResponse>, Status> {
+        let request: proto::UpdateRequest = request.into_inner();
+
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Write, &partition_key)?;
+        let sort_key = convert_sort_key(&self.key_policy, request.sort_key)
+            .map_err(|e| e.at("sort_key"))?;
+        let write_condition = convert_write_condition(request.write_condition)
+            .map_err(|e| e.at("write_condition"))?;
+        let patch = convert_patch(request.patch)
+            .map_err(|e| e.at("patch"))?;
+
+        let item = self.repository.update(context, partition_key, sort_key, write_condition, patch, self.value_limits.max_value_size)
+            .await
+            .map_err(|e| match e {
+                DatabaseError::ValueTooLarge { size, max_size } => ValueTooLarge { size, max_size }.at("patch"),
+                e => write_error(e),
+            })?;
+
+        Ok(Response::new(proto::UpdateResponse {
+            updated: true,
+            item: Some(convert_item(item)),
+        }))
+    }
+
+    async fn handle_put_large(&self, context: RequestContext, request: Request<Streaming<proto::PutLargeRequest>>) -> Result<Response<proto::PutLargeResponse>, Status> {
+        let mut stream = request.into_inner();
+
+        let first = stream.message()
+            .await?
+            .ok_or_else(|| MissingPartitionKey.at("partition_key"))?;
+        let partition_key = convert_partition_key(&self.key_policy, first.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Write, &partition_key)?;
+        let sort_key_is_tuple = is_tuple_sort_key(&first.sort_key);
+        let sort_key = convert_sort_key(&self.key_policy, first.sort_key)
+            .map_err(|e| e.at("sort_key"))?;
+        let write_condition = convert_write_condition(first.write_condition)
+            .map_err(|e| e.at("write_condition"))?;
+
+        // NOTE: Each chunk is written as soon as it is received, and the next one is only read once it is,
+        // so that the value is never held in memory whole. The chunks replace the value of the item once
+        // the stream ends, in a single transaction.
+        let max_size = self.value_limits.max_large_value_size;
+        let mut upload = LargeUpload::new(self.repository.clone(), context);
+        let mut size = 0;
+        let mut chunk = first.chunk;
+        loop {
+            size += chunk.len();
+            check_value_size(size, max_size)
+                .map_err(|e| e.at("chunk"))?;
+            if !chunk.is_empty() {
+                let _reserved = self.large_value_buffer.reserve(chunk.len()).await?;
+                upload.append(chunk)
+                    .await
+                    .map_err(EndpointError::DatabaseError)?;
+            }
+            match stream.message().await? {
+                Some(message) => chunk = message.chunk,
+                None => break,
+            }
+        }
+
+        let version = upload.finish(partition_key, sort_key, sort_key_is_tuple, write_condition)
+            .await
+            .map_err(write_error)?;
+
+        Ok(Response::new(proto::PutLargeResponse {
+            version,
+        }))
+    }
+
+    async fn handle_get_large(&self, context: RequestContext, request: Request<proto::GetLargeRequest>) -> Result<Response<GetLargeStream>, Status> {
+        let request: proto::GetLargeRequest = request.into_inner();
+
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Read, &partition_key)?;
+        let sort_key = convert_sort_key(&self.key_policy, request.sort_key)
+            .map_err(|e| e.at("sort_key"))?;
+
+        let LargeItem { mut item, chunk_count } = self.repository.get_large(context.clone(), partition_key.clone(), sort_key.clone())
+            .await
+            .map_err(EndpointError::DatabaseError)?
+            .ok_or(EndpointError::NotFound)?;
+        let version = item.version;
+        // NOTE: A value written by `Set` has no chunks, and is sent as a single one.
+        let value = std::mem::take(&mut item.value);
+        let first = proto::GetLargeResponse {
+            item: Some(convert_item(item)),
+            chunk: Vec::new(),
+        };
+
+        // NOTE: Each chunk is read by a task of its own once the previous one is sent, so that the value is
+        // never held in memory whole. The stream fails if the item is written meanwhile.
+        let (sender, receiver) = mpsc::channel(1);
+        let repository = self.repository.clone();
+        let large_value_buffer = self.large_value_buffer.clone();
+        tokio::spawn(async move {
+            if sender.send(Ok(first)).await.is_err() {
+                return;
+            }
+            if chunk_count == 0 {
+                let _ = sender.send(Ok(proto::GetLargeResponse { item: None, chunk: value })).await;
+                return;
+            }
+            for chunk_index in 0..chunk_count {
+                let chunk = match repository.get_large_chunk(context.clone(), partition_key.clone(), sort_key.clone(), version, chunk_index).await {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = sender.send(Err(EndpointError::DatabaseError(e).into())).await;
+                        return;
+                    }
+                };
+                // NOTE: The room of the chunk is held until it is queued in the channel, which holds one message.
+                let _reserved = large_value_buffer.reserve(chunk.len()).await;
+                if sender.send(Ok(proto::GetLargeResponse { item: None, chunk })).await.is_err() {
+                    return;
+                }
+            }
+        }.instrument(Span::current()));
 
-        let partition_key: PartitionKey = convert_partition_key(request.partition_key)?;
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn handle_list(&self, context: RequestContext, request: Request<proto::ListRequest>) -> Result<Response<proto::ListResponse>, Status> {
+        let request: proto::ListRequest = request.into_inner();
 
-        let range = request.range
-            .unwrap_or_default();
+        let partition_key: PartitionKey = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Read, &partition_key)?;
 
-        let start = convert_bound(range.start)?;
-        let end = convert_bound(range.end)?;
+        let range = convert_range(&self.key_policy, request.range, request.tuple_prefix, request.prefix)?;
+        let start_after = request.start_after
+            .map(|start_after| convert_sort_key(&self.key_policy, Some(start_after)))
+            .transpose()
+            .map_err(|e| e.at("start_after"))?;
 
         // TODO: Type for page size.
         let page_size = request.page_size as usize;
 
-        let result = self.repository.list(partition_key, (start, end), page_size)
+        let options = ListOptions {
+            start_after,
+            reverse: request.reverse,
+            projection: convert_projection(request.keys_only),
+            include_deleted: request.include_deleted,
+            page_size,
+            snapshot_id: convert_snapshot_id(request.snapshot_id),
+        };
+        let result = self.repository.list(context, partition_key, range, options)
             .await
             .map_err(EndpointError::DatabaseError)?;
 
+        let next_start_after = match result.last() {
+            Some(item) if result.len() == page_size => Some(convert_sort_key_to_proto(item.sort_key.clone())),
+            _ => None,
+        };
         let items = result.into_iter()
-            .map(|item| {
-                let created_at: SystemTime = item.created_at.into();
-                let updated_at: SystemTime = item.updated_at.into();
-
-                proto::Item {
-                    partition_key: Some(proto::PartitionKey {
-                        value: item.partition_key.0,
-                    }),
-                    sort_key: Some(proto::SortKey {
-                        value: item.sort_key.0,
-                    }),
-                    value: item.value,
-                    created_at: Some(created_at.into()),
-                    updated_at: Some(updated_at.into()),
-                    version: item.version,
-                }
-            })
+            .map(convert_item)
             .collect();
 
         Ok(Response::new(proto::ListResponse {
             items,
+            next_start_after,
+        }))
+    }
+
+    async fn handle_count(&self, context: RequestContext, request: Request<proto::CountRequest>) -> Result<Response<proto::CountResponse>, Status> {
+        let request: proto::CountRequest = request.into_inner();
+
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Read, &partition_key)?;
+        let range = convert_range(&self.key_policy, request.range, request.tuple_prefix, request.prefix)?;
+        let max_count = Some(request.max_count)
+            .filter(|max_count| *max_count > 0);
+
+        let count = self.repository.count(context, partition_key, range, max_count)
+            .await
+            .map_err(EndpointError::DatabaseError)?;
+
+        Ok(Response::new(proto::CountResponse {
+            count,
+        }))
+    }
+
+    /// Deletes the range in batches, each executed by the processor as a task of its own, so that other
+    /// tasks run in between. A continuation is returned after `max_batches` batches, or once the deadline
+    /// of the RPC expired, instead of failing after some batches were deleted.
+    async fn handle_delete_range(&self, context: RequestContext, request: Request<proto::DeleteRangeRequest>) -> Result<Response<proto::DeleteRangeResponse>, Status> {
+        let request: proto::DeleteRangeRequest = request.into_inner();
+
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Delete, &partition_key)?;
+
+        let range = request.range.unwrap_or_default();
+        let start = convert_bound(&self.key_policy, range.start)
+            .map_err(|e| e.at("range.start"))?;
+        let end = convert_bound(&self.key_policy, range.end)
+            .map_err(|e| e.at("range.end"))?;
+        let range = SortKeyRange::Bounds(start, end);
+        let mut start_after = request.start_after
+            .map(|start_after| convert_sort_key(&self.key_policy, Some(start_after)))
+            .transpose()
+            .map_err(|e| e.at("start_after"))?;
+
+        let DeleteLimits { batch_size, max_batches } = self.delete_limits;
+        let mut deleted_count = 0;
+        let mut batches = 0;
+        let next_start_after = loop {
+            let batch = self.repository.delete_range(context.clone(), partition_key.clone(), range.clone(), start_after, batch_size)
+                .await
+                .map_err(EndpointError::DatabaseError)?;
+            deleted_count += batch.deleted_count;
+            batches += 1;
+
+            start_after = batch.last_sort_key;
+            if batch.deleted_count < batch_size as u64 {
+                break None;
+            }
+            if batches >= max_batches || context.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break start_after.map(convert_sort_key_to_proto);
+            }
+        };
+
+        Ok(Response::new(proto::DeleteRangeResponse {
+            deleted_count,
+            next_start_after,
+        }))
+    }
+
+    /// Restoring an item reverts its deletion, so it requires the same permission.
+    async fn handle_undelete(&self, context: RequestContext, request: Request<proto::UndeleteRequest>) -> Result<Response<proto::UndeleteResponse>, Status> {
+        let request: proto::UndeleteRequest = request.into_inner();
+
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Delete, &partition_key)?;
+        let sort_key = convert_sort_key(&self.key_policy, request.sort_key)
+            .map_err(|e| e.at("sort_key"))?;
+
+        let item = self.repository.undelete(context, partition_key, sort_key)
+            .await
+            .map_err(EndpointError::DatabaseError)?;
+
+        Ok(Response::new(proto::UndeleteResponse {
+            item: Some(convert_item(item)),
+        }))
+    }
+
+    async fn handle_get_history(&self, context: RequestContext, request: Request<proto::GetHistoryRequest>) -> Result<Response<proto::GetHistoryResponse>, Status> {
+        let request: proto::GetHistoryRequest = request.into_inner();
+
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Read, &partition_key)?;
+        let sort_key = convert_sort_key(&self.key_policy, request.sort_key)
+            .map_err(|e| e.at("sort_key"))?;
+        let before_version = Some(request.before_version)
+            .filter(|before_version| *before_version > 0);
+
+        let page_size = request.page_size as usize;
+
+        let result = self.repository.get_history(context, partition_key, sort_key, before_version, page_size)
+            .await
+            .map_err(EndpointError::DatabaseError)?;
+
+        let next_before_version = match result.last() {
+            Some(item) if result.len() == page_size => item.version,
+            _ => 0,
+        };
+        let items = result.into_iter()
+            .map(convert_item)
+            .collect();
+
+        Ok(Response::new(proto::GetHistoryResponse {
+            items,
+            next_before_version,
+        }))
+    }
+
+    async fn handle_set_history_retention(&self, context: RequestContext, request: Request<proto::SetHistoryRetentionRequest>) -> Result<Response<proto::SetHistoryRetentionResponse>, Status> {
+        let request: proto::SetHistoryRetentionRequest = request.into_inner();
+
+        let partition_key = convert_partition_key(&self.key_policy, request.partition_key)
+            .map_err(|e| e.at("partition_key"))?;
+        self.authorize(&context, Permission::Admin, &partition_key)?;
+        let retention = request.retention
+            .map(convert_history_retention)
+            .transpose()
+            .map_err(|e| e.at("retention"))?;
+
+        self.repository.set_history_retention(context, partition_key, retention)
+            .await
+            .map_err(EndpointError::DatabaseError)?;
+
+        Ok(Response::new(proto::SetHistoryRetentionResponse {}))
+    }
+
+    /// Snapshots are not bound to partitions, so they require no permission: reads made in a snapshot
+    /// are authorized like any other, and only the identity that opened it can use it.
+    async fn handle_begin_snapshot(&self, context: RequestContext, _request: Request<proto::BeginSnapshotRequest>) -> Result<Response<proto::BeginSnapshotResponse>, Status> {
+        let snapshot = self.repository.begin_snapshot(context)
+            .await
+            .map_err(EndpointError::DatabaseError)?;
+
+        Ok(Response::new(proto::BeginSnapshotResponse {
+            snapshot_id: snapshot.snapshot_id.0,
+            lease: prost_types::Duration::try_from(snapshot.lease).ok(),
+        }))
+    }
+
+    async fn handle_end_snapshot(&self, context: RequestContext, request: Request<proto::EndSnapshotRequest>) -> Result<Response<proto::EndSnapshotResponse>, Status> {
+        let request: proto::EndSnapshotRequest = request.into_inner();
+
+        self.repository.end_snapshot(context, SnapshotId(request.snapshot_id))
+            .await
+            .map_err(EndpointError::DatabaseError)?;
+
+        Ok(Response::new(proto::EndSnapshotResponse {}))
+    }
+
+    /// The partitions the caller may not read are left out of the page, but still move the listing
+    /// forward, so that `next_start_after` does not depend on the permissions.
+    async fn handle_list_partitions(&self, context: RequestContext, request: Request<proto::ListPartitionsRequest>) -> Result<Response<proto::ListPartitionsResponse>, Status> {
+        let request: proto::ListPartitionsRequest = request.into_inner();
+
+        let prefix = request.prefix
+            .map(|prefix| convert_partition_key(&self.key_policy, Some(prefix)))
+            .transpose()
+            .map_err(|e| e.at("prefix"))?;
+        let mut start_after = request.start_after
+            .map(|start_after| convert_partition_key(&self.key_policy, Some(start_after)))
+            .transpose()
+            .map_err(|e| e.at("start_after"))?;
+
+        let page_size = request.page_size as usize;
+
+        // NOTE: The partitions the caller may not read are skipped, and more are listed in their place until
+        // the page is full, so that the page ends with a partition the caller may read and the key of no
+        // other partition is returned, not even as `next_start_after`.
+        let mut result = Vec::with_capacity(page_size);
+        while result.len() < page_size {
+            let remaining = page_size - result.len();
+            let partitions = self.repository.list_partitions(context.clone(), prefix.clone(), start_after.take(), request.include_item_count, remaining)
+                .await
+                .map_err(EndpointError::DatabaseError)?;
+            let exhausted = partitions.len() < remaining;
+            start_after = partitions.last().map(|partition| partition.partition_key.clone());
+            result.extend(partitions.into_iter()
+                .filter(|partition| self.acl.is_allowed(context.identity.as_ref(), Permission::Read, &partition.partition_key)));
+            if exhausted {
+                break;
+            }
+        }
+
+        let next_start_after = match result.last() {
+            Some(partition) if result.len() == page_size => Some(convert_partition_key_to_proto(partition.partition_key.clone())),
+            _ => None,
+        };
+        let partitions = result.into_iter()
+            .map(convert_partition)
+            .collect();
+
+        Ok(Response::new(proto::ListPartitionsResponse {
+            partitions,
+            next_start_after,
         }))
     }
 }
-fn convert_partition_key(key: Option<proto::PartitionKey>) -> Result<PartitionKey, EndpointError> {
-    key
-        .ok_or(MissingPartitionKey)?
-        .value
-        .try_into()
-        .map_err(|_| InvalidPartitionKey)
+/// Uses the request ID provided by the client if any, so that its logs can be correlated with ours.
+fn request_context<T>(request: &Request<T>) -> RequestContext {
+    let request_id = request.metadata()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| RequestId(value.to_string()))
+        .unwrap_or_else(RequestId::generate);
+
+    RequestContext {
+        request_id,
+        // NOTE: A bearer token takes precedence over the client certificate.
+        identity: request.extensions()
+            .get::<Identity>()
+            .cloned()
+            .or_else(|| tls::peer_identity(request)),
+        deadline: request.metadata()
+            .get(TIMEOUT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_timeout)
+            .map(|timeout| Instant::now() + timeout),
+    }
+}
+
+/// Parses a `grpc-timeout` value: at most 8 digits followed by a unit (`H`, `M`, `S`, `m`, `u` or `n`).
+fn parse_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
 }
 
+/// Name of the client used for rate limiting: its identity, or its address when it is not
+/// authenticated.
+fn client_name<T>(context: &RequestContext, request: &Request<T>) -> String {
+    match (&context.identity, request.remote_addr()) {
+        (Some(identity), _) => identity.to_string(),
+        (None, Some(addr)) => addr.ip().to_string(),
+        (None, None) => "anonymous".to_string(),
+    }
+}
 
-fn convert_sort_key(key: Option<proto::SortKey>) -> Result<SortKey, EndpointError> {
-    key
-        .ok_or(MissingSortKey)?
-        .value
-        .try_into()
-        .map_err(|_| InvalidSortKey)
+/// A key without a value is an empty text key, which is what older clients sent for a missing one.
+fn convert_partition_key(key_policy: &KeyPolicy, key: Option<proto::PartitionKey>) -> Result<PartitionKey, EndpointError> {
+    let key = match key.ok_or(MissingPartitionKey)?.value {
+        Some(proto::partition_key::Value::Text(text)) => Key::Text(text),
+        Some(proto::partition_key::Value::Binary(bytes)) => Key::Bytes(bytes),
+        None => Key::Text(String::new()),
+    };
+    key_policy.partition_key(key)
+        .map_err(InvalidPartitionKey)
+}
+
+
+fn convert_sort_key(key_policy: &KeyPolicy, key: Option<proto::SortKey>) -> Result<SortKey, EndpointError> {
+    let key = match key.ok_or(MissingSortKey)?.value {
+        Some(proto::sort_key::Value::Text(text)) => Key::Text(text),
+        Some(proto::sort_key::Value::Binary(bytes)) => Key::Bytes(bytes),
+        Some(proto::sort_key::Value::Tuple(tuple)) => {
+            let elements = convert_tuple(tuple).map_err(InvalidSortKey)?;
+            Key::Bytes(tuple::encode(&elements))
+        }
+        None => Key::Text(String::new()),
+    };
+    key_policy.sort_key(key)
+        .map_err(InvalidSortKey)
+}
+
+fn is_tuple_sort_key(key: &Option<proto::SortKey>) -> bool {
+    matches!(key, Some(proto::SortKey { value: Some(proto::sort_key::Value::Tuple(_)) }))
+}
+
+fn convert_tuple(tuple: proto::TupleKey) -> Result<Vec<TupleElement>, InvalidKeyReason> {
+    tuple.elements
+        .into_iter()
+        .enumerate()
+        .map(|(index, element)| match element.value {
+            Some(proto::tuple_element::Value::Binary(bytes)) => Ok(TupleElement::Bytes(bytes)),
+            Some(proto::tuple_element::Value::Text(text)) => Ok(TupleElement::String(text)),
+            Some(proto::tuple_element::Value::Integer(integer)) => Ok(TupleElement::Integer(integer)),
+            Some(proto::tuple_element::Value::Boolean(boolean)) => Ok(TupleElement::Bool(boolean)),
+            None => Err(InvalidKeyReason::MissingTupleElement { index }),
+        })
+        .collect()
+}
+
+/// The tuples starting with some elements are the binary keys starting with their encoding, since the
+/// encoding of each element is prefix-free.
+///
+/// NOTE: The prefix is not checked against the key policy, so that an empty tuple lists every tuple.
+fn convert_tuple_prefix(tuple_prefix: proto::TupleKey) -> Result<SortKeyRange, EndpointError> {
+    let elements = convert_tuple(tuple_prefix).map_err(InvalidSortKey)?;
+    Ok(SortKeyRange::Prefix(SortKey(Key::Bytes(tuple::encode(&elements)))))
+}
+
+/// Converts the items selected by a `List` or a `Count`: a range, a prefix or a tuple prefix.
+fn convert_range(key_policy: &KeyPolicy, range: Option<proto::Range>, tuple_prefix: Option<proto::TupleKey>, prefix: Option<proto::SortKey>) -> Result<SortKeyRange, EndpointError> {
+    match (range, tuple_prefix, prefix) {
+        (None, Some(tuple_prefix), None) => convert_tuple_prefix(tuple_prefix)
+            .map_err(|e| e.at("tuple_prefix")),
+        (None, None, Some(prefix)) => {
+            let prefix = convert_sort_key(key_policy, Some(prefix))
+                .map_err(|e| e.at("prefix"))?;
+            Ok(SortKeyRange::Prefix(prefix))
+        }
+        (range, None, None) => {
+            let range = range.unwrap_or_default();
+            let start = convert_bound(key_policy, range.start)
+                .map_err(|e| e.at("range.start"))?;
+            let end = convert_bound(key_policy, range.end)
+                .map_err(|e| e.at("range.end"))?;
+            Ok(SortKeyRange::Bounds(start, end))
+        }
+        _ => Err(InvalidRange("range, prefix and tuple_prefix are mutually exclusive").at("prefix")),
+    }
+}
+
+fn convert_projection(keys_only: bool) -> Projection {
+    if keys_only {
+        Projection::KeysOnly
+    } else {
+        Projection::Full
+    }
+}
+
+fn convert_tuple_elements(elements: Vec<TupleElement>) -> proto::TupleKey {
+    let elements = elements.into_iter()
+        .map(|element| proto::TupleElement {
+            value: Some(match element {
+                TupleElement::Bytes(bytes) => proto::tuple_element::Value::Binary(bytes),
+                TupleElement::String(text) => proto::tuple_element::Value::Text(text),
+                TupleElement::Integer(integer) => proto::tuple_element::Value::Integer(integer),
+                TupleElement::Bool(boolean) => proto::tuple_element::Value::Boolean(boolean),
+            }),
+        })
+        .collect();
+    proto::TupleKey { elements }
+}
+
+fn check_value_size(size: usize, max_size: usize) -> Result<(), EndpointError> {
+    if size > max_size {
+        Err(ValueTooLarge { size, max_size })
+    } else {
+        Ok(())
+    }
+}
+
+fn convert_write_condition(write_condition: Option<proto::WriteCondition>) -> Result<WriteCondition, EndpointError> {
+    let Some(write_condition) = write_condition else {
+        return Ok(WriteCondition::default());
+    };
+
+    let existence = match proto::Existence::try_from(write_condition.existence) {
+        Ok(proto::Existence::Unspecified) => None,
+        Ok(proto::Existence::Exists) => Some(Existence::Exists),
+        Ok(proto::Existence::NotExists) => Some(Existence::NotExists),
+        Err(_) => return Err(InvalidWriteCondition("unknown existence")),
+    };
+
+    let updated_before = write_condition.updated_before
+        .map(|updated_before| {
+            SystemTime::try_from(updated_before)
+                .map(OffsetDateTime::from)
+                .map_err(|_| InvalidWriteCondition("invalid updated_before"))
+        })
+        .transpose()?;
+
+    let value_hash_equals = match write_condition.value_hash_equals.as_slice() {
+        [] => None,
+        hash => Some(hash.try_into().map_err(|_| InvalidWriteCondition("value_hash_equals must be a SHA-256"))?),
+    };
+
+    Ok(WriteCondition {
+        version_equals: write_condition.version_equals,
+        existence,
+        version_less_than: write_condition.version_less_than,
+        updated_before,
+        value_hash_equals,
+    })
+}
+
+/// An empty snapshot ID reads the latest state.
+fn convert_snapshot_id(snapshot_id: String) -> Option<SnapshotId> {
+    Some(snapshot_id)
+        .filter(|snapshot_id| !snapshot_id.is_empty())
+        .map(SnapshotId)
+}
+
+fn convert_history_retention(retention: proto::HistoryRetention) -> Result<HistoryRetention, EndpointError> {
+    let max_age = retention.max_age
+        .map(|max_age| Duration::try_from(max_age).map_err(|_| InvalidHistoryRetention("max_age must not be negative")))
+        .transpose()?;
+
+    Ok(HistoryRetention {
+        max_versions: Some(u64::from(retention.max_versions)).filter(|max_versions| *max_versions > 0),
+        max_age,
+    })
+}
+
+fn convert_write_mode(write_mode: i32, write_condition: &WriteCondition) -> Result<WriteMode, EndpointError> {
+    match proto::WriteMode::try_from(write_mode) {
+        Ok(proto::WriteMode::Upsert) => Ok(WriteMode::Upsert),
+        Ok(proto::WriteMode::InsertOnly) => Ok(WriteMode::InsertOnly),
+        Ok(proto::WriteMode::UpdateOnly) => Ok(WriteMode::UpdateOnly),
+        Ok(proto::WriteMode::ReplaceIfVersion) => match write_condition.version_equals {
+            Some(version) if version > 0 => Ok(WriteMode::ReplaceIfVersion(version)),
+            _ => Err(InvalidWriteMode("REPLACE_IF_VERSION requires a non-zero version_equals")),
+        },
+        Err(_) => Err(InvalidWriteMode("unknown write mode")),
+    }
+}
+
+fn convert_patch(patch: Option<proto::update_request::Patch>) -> Result<Patch, EndpointError> {
+    match patch.ok_or(MissingPatch)? {
+        proto::update_request::Patch::MergePatch(patch) => serde_json::from_slice(&patch).map(Patch::Merge),
+        proto::update_request::Patch::JsonPatch(patch) => serde_json::from_slice(&patch).map(Patch::Json),
+    }
+        .map_err(EndpointError::InvalidPatch)
+}
+
+/// Attributes the conflicts of a `Set` or an `Update` to the write condition or write mode that
+/// caused them.
+fn write_error(error: DatabaseError) -> EndpointError {
+    if let DatabaseError::IdempotencyKeyReused = error {
+        return EndpointError::DatabaseError(error).at("idempotency_key");
+    }
+    let DatabaseError::PreconditionFailed { index, failure } = &error else {
+        return EndpointError::DatabaseError(error);
+    };
+
+    let name = match failure {
+        PreconditionFailure::ConditionNotMet => "write_condition",
+        _ => "write_mode",
+    };
+    let field = match index {
+        Some(index) => format!("set_values[{}].{}", index, name),
+        None => name.to_string(),
+    };
+    EndpointError::DatabaseError(error).at(field)
+}
+
+fn convert_item(item: Item) -> proto::Item {
+    let created_at: SystemTime = item.created_at.into();
+    let updated_at: SystemTime = item.updated_at.into();
+
+    // NOTE: A binary key written as bytes may happen to be a valid encoding of a tuple, so only the keys
+    // written as tuples are decoded.
+    let sort_key_tuple = match &item.sort_key.0 {
+        Key::Bytes(bytes) if item.sort_key_is_tuple => tuple::decode(bytes).map(convert_tuple_elements),
+        _ => None,
+    };
+
+    proto::Item {
+        partition_key: Some(convert_partition_key_to_proto(item.partition_key)),
+        sort_key: Some(convert_sort_key_to_proto(item.sort_key)),
+        value: item.value,
+        created_at: Some(created_at.into()),
+        updated_at: Some(updated_at.into()),
+        version: item.version,
+        large_value_size: item.large_value_size.unwrap_or(0),
+        sort_key_tuple,
+        deleted_at: item.deleted_at.map(|deleted_at| SystemTime::from(deleted_at).into()),
+    }
+}
+
+fn convert_partition(partition: Partition) -> proto::Partition {
+    proto::Partition {
+        partition_key: Some(convert_partition_key_to_proto(partition.partition_key)),
+        item_count: partition.item_count.unwrap_or(0),
+    }
+}
+
+fn convert_partition_key_to_proto(partition_key: PartitionKey) -> proto::PartitionKey {
+    proto::PartitionKey {
+        value: Some(match partition_key.0 {
+            Key::Text(text) => proto::partition_key::Value::Text(text),
+            Key::Bytes(bytes) => proto::partition_key::Value::Binary(bytes),
+        }),
+    }
+}
+
+fn convert_sort_key_to_proto(sort_key: SortKey) -> proto::SortKey {
+    let value = match sort_key.0 {
+        Key::Text(text) => proto::sort_key::Value::Text(text),
+        Key::Bytes(bytes) => proto::sort_key::Value::Binary(bytes),
+    };
+    proto::SortKey {
+        value: Some(value),
+    }
 }
 
-fn convert_bound(b: Option<proto::Bound>) -> Result<Bound<SortKey>, EndpointError> {
+fn convert_bound(key_policy: &KeyPolicy, b: Option<proto::Bound>) -> Result<Bound<SortKey>, EndpointError> {
     let b = b.and_then(|b| b.bound);
     let bound = match b {
         Some(proto::bound::Bound::Included(sort_key)) => {
-            let sort_key = convert_sort_key(Some(sort_key))?;
+            let sort_key = convert_sort_key(key_policy, Some(sort_key))?;
             Included(sort_key)
         }
         Some(proto::bound::Bound::Excluded(sort_key)) => {
-            let sort_key = convert_sort_key(Some(sort_key))?;
+            let sort_key = convert_sort_key(key_policy, Some(sort_key))?;
             Excluded(sort_key)
         }
         Some(proto::bound::Bound::Unbounded(_)) =>
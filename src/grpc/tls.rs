@@ -0,0 +1,142 @@
+use crate::config::TlsConfig;
+use crate::grpc::watch;
+use crate::model::identity::Identity;
+use std::path::Path;
+use tonic::transport::{Certificate, Identity as ServerIdentity, Server, ServerTlsConfig};
+use tonic::Request;
+use tracing::{info, warn};
+use x509_parser::extensions::GeneralName;
+
+pub fn load(config: &TlsConfig) -> std::io::Result<ServerTlsConfig> {
+    let cert = std::fs::read(&config.cert_path)?;
+    let key = std::fs::read(&config.key_path)?;
+    let mut tls_config = ServerTlsConfig::new()
+        .identity(ServerIdentity::from_pem(cert, key));
+
+    if let Some(client_ca_path) = &config.client_ca_path {
+        let client_ca = std::fs::read(client_ca_path)?;
+        tls_config = tls_config.client_ca_root(Certificate::from_pem(client_ca));
+    }
+
+    Ok(tls_config)
+}
+
+/// Resolves once one of the certificate files has changed on disk and can be loaded, with the loaded
+/// certificates.
+///
+/// Files that change but fail to load (e.g. while being written) are ignored until they change again.
+pub async fn wait_for_change(config: &TlsConfig) -> ServerTlsConfig {
+    let mut paths: Vec<&Path> = vec![&config.cert_path, &config.key_path];
+    if let Some(client_ca_path) = &config.client_ca_path {
+        paths.push(client_ca_path);
+    }
+
+    let mut loaded = watch::modification_times(&paths);
+    loop {
+        loaded = watch::wait_for_modification(&paths, &loaded).await;
+
+        match load(config).map(|tls_config| (Server::builder().tls_config(tls_config.clone()), tls_config)) {
+            Ok((Ok(_), tls_config)) => {
+                info!("TLS certificates changed on disk");
+                return tls_config;
+            }
+            Ok((Err(e), _)) => warn!(error = %e, "ignoring invalid TLS certificates"),
+            Err(e) => warn!(error = %e, "failed to read TLS certificates"),
+        }
+    }
+}
+
+/// Identity of the client, taken from the leaf certificate it presented during the TLS handshake.
+pub fn peer_identity<T>(request: &Request<T>) -> Option<Identity> {
+    let certs = request.peer_certs()?;
+    certificate_identity(certs.first()?)
+}
+
+/// Identity named by a DER-encoded certificate: its subject, and the subject alternative names that
+/// identify a client.
+fn certificate_identity(der: &[u8]) -> Option<Identity> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+
+    let subject_alt_names = cert.subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|extension| {
+            extension.value.general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some(format!("DNS:{}", name)),
+                    GeneralName::URI(uri) => Some(format!("URI:{}", uri)),
+                    GeneralName::RFC822Name(email) => Some(format!("email:{}", email)),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some(Identity::Certificate {
+        subject: cert.subject().to_string(),
+        subject_alt_names,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::task;
+    use uuid::Uuid;
+
+    const CERT: &str = include_str!("../../tests/fixtures/tls/cert.pem");
+    const KEY: &str = include_str!("../../tests/fixtures/tls/key.pem");
+
+    #[test]
+    fn certificate_identity_has_the_subject_and_the_names_of_the_client() {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(CERT.as_bytes()).unwrap();
+
+        let identity = certificate_identity(&pem.contents).unwrap();
+
+        assert_eq!(identity, Identity::Certificate {
+            subject: "O=Parapluie, CN=client-1".to_string(),
+            subject_alt_names: vec![
+                "DNS:client.example.com".to_string(),
+                "URI:spiffe://example.com/client-1".to_string(),
+                "email:client-1@example.com".to_string(),
+            ],
+        });
+    }
+
+    #[test]
+    fn certificate_identity_is_none_for_invalid_certificates() {
+        assert_eq!(certificate_identity(b"not a certificate"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn certificates_that_fail_to_load_are_ignored_until_valid_ones_replace_them() {
+        let directory = std::env::temp_dir().join(format!("parapluie-{}", Uuid::new_v4()));
+        std::fs::create_dir(&directory).unwrap();
+        let config = TlsConfig {
+            cert_path: directory.join("cert.pem"),
+            key_path: directory.join("key.pem"),
+            client_ca_path: None,
+        };
+        std::fs::write(&config.cert_path, CERT).unwrap();
+        std::fs::write(&config.key_path, KEY).unwrap();
+
+        let change = task::spawn({
+            let config = config.clone();
+            async move { wait_for_change(&config).await }
+        });
+        // NOTE: Lets the watcher read the modification times of the valid files before they change.
+        task::yield_now().await;
+
+        std::fs::write(&config.cert_path, "not a certificate").unwrap();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert!(!change.is_finished());
+
+        std::fs::write(&config.cert_path, CERT).unwrap();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert!(change.is_finished());
+
+        let _ = std::fs::remove_dir_all(directory);
+    }
+}
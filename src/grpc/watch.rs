@@ -0,0 +1,24 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::time;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Polls the files until their modification times differ from the given ones, and returns the new
+/// modification times.
+pub async fn wait_for_modification(paths: &[&Path], loaded: &[Option<SystemTime>]) -> Vec<Option<SystemTime>> {
+    loop {
+        time::sleep(POLL_INTERVAL).await;
+
+        let current = modification_times(paths);
+        if current != loaded {
+            return current;
+        }
+    }
+}
+
+pub fn modification_times(paths: &[&Path]) -> Vec<Option<SystemTime>> {
+    paths.iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
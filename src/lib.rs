@@ -0,0 +1,8 @@
+pub mod repository;
+pub mod proto;
+pub mod grpc;
+pub mod model;
+pub mod error;
+pub mod config;
+pub mod metrics;
+pub mod telemetry;
@@ -1,53 +1,65 @@
-use crate::error::app::AppError;
-use crate::grpc::Service;
-use crate::proto::parapluie::parapluie_db_server::ParapluieDbServer;
-use crate::proto::parapluie::FILE_DESCRIPTOR_SET;
-use crate::repository::{Processor, Repository};
+use parapluie::config::Config;
+use parapluie::error::app::AppError;
+use parapluie::grpc::{self, Acl, AuthInterceptor, RateLimiter, Service, TokenStore};
+use parapluie::metrics::{self, Metrics};
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDbServer;
+use parapluie::proto::parapluie::FILE_DESCRIPTOR_SET;
+use parapluie::repository::{functions, schema, sweep, Processor, Repository, Snapshots};
+use parapluie::telemetry;
 use rusqlite::Connection;
+use std::sync::{Arc, RwLock};
 use tokio::sync::mpsc;
 use tokio::{select, task};
-use tonic::transport::Server;
-use tracing_subscriber::FmtSubscriber;
-
-mod repository;
-mod proto;
-mod grpc;
-mod model;
-mod error;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Routes;
+use tracing::{error, info};
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    let x =1;
-    let subscriber = FmtSubscriber::builder().finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+    let config = Config::from_env()?;
+    let _telemetry = telemetry::init(&config)?;
 
+    let metrics = Metrics::new(config.database_path.clone())?;
 
-    let (sender, receiver) = mpsc::channel(32);
-    let repository = Repository::new(sender).await;
-    let grpc_service = Service::new(repository);
-    let listen_addr = "0.0.0.0:50051".parse()?;
-    let server = ParapluieDbServer::new(grpc_service);
+    let (sender, receiver) = mpsc::channel(config.queue_capacity());
+    let repository = Repository::new(sender, metrics.clone(), config.max_queue_depth).await;
+    let acl = match &config.acl_file {
+        Some(acl_file) => Acl::load(acl_file)?,
+        None => Acl::default(),
+    };
+    let rate_limiter = RateLimiter::new(config.rate_limit);
+    tokio::spawn(sweep(repository.clone(), config.tombstone_retention, config.delete_limits.batch_size));
+    let grpc_service = Service::new(repository, metrics.clone(), acl, rate_limiter, config.key_policy, config.value_limits, config.delete_limits);
+    let auth_interceptor = match &config.token_file {
+        Some(token_file) => {
+            let tokens = Arc::new(RwLock::new(TokenStore::load(token_file)?));
+            tokio::spawn(grpc::watch_token_file(token_file.clone(), tokens.clone()));
+            AuthInterceptor::new(tokens, metrics.clone())
+        }
+        None => AuthInterceptor::default(),
+    };
+    let server = ParapluieDbServer::new(grpc_service)
+        .max_decoding_message_size(config.max_message_size)
+        .max_encoding_message_size(config.max_message_size);
+    let server = InterceptedService::new(server, auth_interceptor);
 
+    let processor_metrics = metrics.clone();
+    let database_path = config.database_path.clone();
+    let max_queue_wait = config.max_queue_wait;
+    let idempotency_window = config.idempotency_window;
+    let snapshot_limits = config.snapshot_limits;
     let sqlite_task = task::spawn_blocking(move || -> Result<(), AppError> {
         // NOTE: The connection must be opened in the same thread as the processor.
-        let conn = Connection::open("/tmp/db.sqlite")?;
+        let conn = Connection::open(&database_path)?;
 
         conn.pragma_update(None, "journal_mode", "WAL")?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS item (
-                partition_key TEXT NOT NULL,
-                sort_key TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                version INTEGER NOT NULL,
-                value BLOB NOT NULL,
-                PRIMARY KEY (partition_key, sort_key)
-            )",
-            [],
-        )?;
+        schema::create(&conn)?;
+        functions::register(&conn)?;
 
-        let processor = Processor::new(conn, receiver);
+        // NOTE: Snapshots open their own connections, once the schema exists.
+        let snapshots = Snapshots::new(database_path, snapshot_limits);
+        let processor = Processor::new(conn, snapshots, receiver, processor_metrics, max_queue_wait, idempotency_window);
         processor.blocking_process_tasks()?;
 
         Ok(())
@@ -60,20 +72,27 @@ async fn main() -> Result<(), AppError> {
 
     // NOTE: Once the server stops, the processor will stop as well because the sender will be
     // dropped.
-    let grpc_server = Server::builder()
-        .add_service(server)
-        .add_service(reflection_service)
-        .serve(listen_addr);
+    let routes = Routes::new(server)
+        .add_service(reflection_service);
+    let grpc_server = grpc::serve(config.listen_addr, config.tls.clone(), routes);
+
+    let metrics_server = metrics::serve(config.metrics_addr, metrics);
+
+    info!(listen_addr = %config.listen_addr, tls = config.tls.is_some(), metrics_addr = %config.metrics_addr, "starting server");
 
     select! {
         result = grpc_server => {
-            println!("gRPC server stopped: ${:?}", result);
+            error!(?result, "gRPC server stopped");
             result?
         }
         result = sqlite_task => {
-            println!("SQLite task stopped: ${:?}", result);
+            error!(?result, "SQLite task stopped");
             result??
         }
+        result = metrics_server => {
+            error!(?result, "metrics server stopped");
+            result?
+        }
     }
 
     Ok(())
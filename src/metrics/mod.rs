@@ -0,0 +1,5 @@
+mod registry;
+mod server;
+
+pub use registry::Metrics;
+pub use server::{serve, serve_on};
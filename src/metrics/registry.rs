@@ -0,0 +1,153 @@
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::time::Duration;
+use tonic::Code;
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    database_path: PathBuf,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    unauthenticated_requests: IntCounter,
+    queue_depth: IntGauge,
+    task_duration: HistogramVec,
+    transactions: IntCounterVec,
+    conditional_write_failures: IntCounter,
+    open_snapshots: IntGauge,
+    file_size: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new(database_path: PathBuf) -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("parapluie".to_string()), None)?;
+
+        let requests = IntCounterVec::new(
+            Opts::new("grpc_requests_total", "Number of gRPC requests handled, by method and status code."),
+            &["method", "code"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("grpc_request_duration_seconds", "Latency of gRPC requests, by method."),
+            &["method"],
+        )?;
+        let unauthenticated_requests = IntCounter::new(
+            "grpc_unauthenticated_requests_total",
+            "Number of gRPC requests rejected without a valid bearer token, before reaching their method.",
+        )?;
+        let queue_depth = IntGauge::new(
+            "processor_queue_depth",
+            "Number of tasks waiting in the queue between the repository and the processor.",
+        )?;
+        let task_duration = HistogramVec::new(
+            HistogramOpts::new("processor_task_duration_seconds", "Execution time of processor tasks, by task."),
+            &["task"],
+        )?;
+        let transactions = IntCounterVec::new(
+            Opts::new("transactions_total", "Number of write transactions, by outcome."),
+            &["outcome"],
+        )?;
+        let conditional_write_failures = IntCounter::new(
+            "conditional_write_failures_total",
+            "Number of write transactions rolled back because a write condition was not met.",
+        )?;
+        let open_snapshots = IntGauge::new(
+            "open_snapshots",
+            "Number of snapshots open, each holding a read transaction.",
+        )?;
+        let file_size = IntGaugeVec::new(
+            Opts::new("sqlite_file_size_bytes", "Size of the SQLite files on disk, by file."),
+            &["file"],
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(unauthenticated_requests.clone()))?;
+        registry.register(Box::new(queue_depth.clone()))?;
+        registry.register(Box::new(task_duration.clone()))?;
+        registry.register(Box::new(transactions.clone()))?;
+        registry.register(Box::new(conditional_write_failures.clone()))?;
+        registry.register(Box::new(open_snapshots.clone()))?;
+        registry.register(Box::new(file_size.clone()))?;
+
+        Ok(Self {
+            registry,
+            database_path,
+            requests,
+            request_duration,
+            unauthenticated_requests,
+            queue_depth,
+            task_duration,
+            transactions,
+            conditional_write_failures,
+            open_snapshots,
+            file_size,
+        })
+    }
+
+    pub fn observe_request(&self, method: &str, code: Code, elapsed: Duration) {
+        let code = format!("{:?}", code);
+        self.requests.with_label_values(&[method, &code]).inc();
+        self.request_duration.with_label_values(&[method]).observe(elapsed.as_secs_f64());
+    }
+
+    pub fn request_unauthenticated(&self) {
+        self.unauthenticated_requests.inc();
+    }
+
+    pub fn task_enqueued(&self) {
+        self.queue_depth.inc();
+    }
+
+    pub fn task_dequeued(&self) {
+        self.queue_depth.dec();
+    }
+
+    pub fn observe_task(&self, task: &str, elapsed: Duration) {
+        self.task_duration.with_label_values(&[task]).observe(elapsed.as_secs_f64());
+    }
+
+    pub fn transaction_committed(&self) {
+        self.transactions.with_label_values(&["commit"]).inc();
+    }
+
+    pub fn transaction_rolled_back(&self) {
+        self.transactions.with_label_values(&["rollback"]).inc();
+    }
+
+    pub fn conditional_write_failed(&self) {
+        self.conditional_write_failures.inc();
+    }
+
+    pub fn set_open_snapshots(&self, count: usize) {
+        self.open_snapshots.set(count as i64);
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        // NOTE: File sizes are sampled at scrape time rather than after every write.
+        let mut wal_path = self.database_path.clone().into_os_string();
+        wal_path.push("-wal");
+
+        self.file_size.with_label_values(&["database"]).set(file_size(&self.database_path));
+        self.file_size.with_label_values(&["wal"]).set(file_size(&PathBuf::from(wal_path)));
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
+
+impl Debug for Metrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics")
+            .field("database_path", &self.database_path)
+            .finish_non_exhaustive()
+    }
+}
+
+fn file_size(path: &PathBuf) -> i64 {
+    std::fs::metadata(path)
+        .map(|metadata| metadata.len() as i64)
+        .unwrap_or(0)
+}
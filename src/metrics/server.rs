@@ -0,0 +1,27 @@
+use crate::metrics::Metrics;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Serves the metrics in the Prometheus text format on `GET /metrics`.
+pub async fn serve(addr: SocketAddr, metrics: Metrics) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    serve_on(listener, metrics).await
+}
+
+/// Serves the metrics on a listener that is already bound, e.g. to an ephemeral port.
+pub async fn serve_on(listener: TcpListener, metrics: Metrics) -> std::io::Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(render))
+        .with_state(metrics);
+
+    axum::serve(listener, app).await
+}
+
+async fn render(State(metrics): State<Metrics>) -> Result<String, (StatusCode, String)> {
+    metrics.encode()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
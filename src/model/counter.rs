@@ -0,0 +1,14 @@
+/// Value of an item holding a counter, encoded as a big-endian 64-bit signed integer.
+#[derive(Clone, Copy, Debug)]
+pub struct Counter {
+    pub value: i64,
+    pub version: u64,
+}
+
+pub fn encode(value: i64) -> Vec<u8> {
+    value.to_be_bytes().to_vec()
+}
+
+pub fn decode(value: &[u8]) -> Option<i64> {
+    value.try_into().ok().map(i64::from_be_bytes)
+}
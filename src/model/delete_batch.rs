@@ -0,0 +1,9 @@
+use crate::model::sort_key::SortKey;
+
+/// Items deleted by one batch of a `DeleteRange`.
+#[derive(Clone, Debug)]
+pub struct DeleteBatch {
+    pub deleted_count: u64,
+    /// Sort key of the last deleted item, after which the next batch continues.
+    pub last_sort_key: Option<SortKey>,
+}
@@ -0,0 +1,14 @@
+use crate::model::projection::Projection;
+use crate::model::snapshot::SnapshotId;
+
+/// How the item of a `Get` is returned. The default reads the whole item, unless it is deleted.
+#[derive(Clone, Debug, Default)]
+pub struct GetOptions {
+    pub projection: Projection,
+    /// Returns the item even if it is deleted, in which case its `deleted_at` is set.
+    pub include_deleted: bool,
+    /// Reads this version of the item, from its history when it is not the current one.
+    pub version: Option<u64>,
+    /// Reads the item as of the snapshot instead of its latest state.
+    pub snapshot_id: Option<SnapshotId>,
+}
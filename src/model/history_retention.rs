@@ -0,0 +1,10 @@
+use std::time::Duration;
+
+/// How long the previous versions of the items of a partition are kept in their history.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HistoryRetention {
+    /// Number of versions before the current one kept for each item; no limit when unset.
+    pub max_versions: Option<u64>,
+    /// How long a version is kept once it was replaced; no limit when unset.
+    pub max_age: Option<Duration>,
+}
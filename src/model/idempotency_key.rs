@@ -0,0 +1,7 @@
+/// Key sent with a `Set` so that it can be retried safely, along with the request it was sent with.
+#[derive(Clone, Debug)]
+pub struct IdempotencyKey {
+    pub key: String,
+    /// SHA-256 of the request, which tells a retry apart from a different write reusing the key.
+    pub request_hash: Vec<u8>,
+}
@@ -0,0 +1,24 @@
+use std::fmt::{Display, Formatter};
+
+/// Authenticated identity of the client that sent a request.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Identity {
+    /// Client authenticated with a TLS client certificate.
+    Certificate {
+        subject: String,
+        subject_alt_names: Vec<String>,
+    },
+    /// Client authenticated with a bearer token from the token file.
+    Token {
+        name: String,
+    },
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Identity::Certificate { subject, .. } => write!(f, "certificate:{}", subject),
+            Identity::Token { name } => write!(f, "token:{}", name),
+        }
+    }
+}
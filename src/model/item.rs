@@ -6,9 +6,16 @@ use crate::model::sort_key::SortKey;
 pub struct Item {
     pub partition_key: PartitionKey,
     pub sort_key: SortKey,
+    /// Whether the sort key was written as a tuple, rather than as the bytes of its encoding.
+    pub sort_key_is_tuple: bool,
     pub created_at: OffsetDateTime,
     pub updated_at: OffsetDateTime,
     pub version: u64,
+    /// Empty for values written in chunks by `PutLarge`.
     pub value: Vec<u8>,
+    /// Size of the value when it was written in chunks.
+    pub large_value_size: Option<u64>,
+    /// Set on the tombstones of deleted items, which are only returned when asked for.
+    pub deleted_at: Option<OffsetDateTime>,
 }
 
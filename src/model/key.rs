@@ -0,0 +1,38 @@
+use std::fmt::{Display, Formatter};
+
+/// Value of a partition or sort key, either text or bytes.
+///
+/// NOTE: The variants are ordered the way SQLite orders the values of a column: text sorts before
+/// bytes, and both are compared byte-wise (memcmp).
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
+pub enum Key {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Key {
+    /// Length of the key, in bytes (of UTF-8 for text keys).
+    pub fn len(&self) -> usize {
+        match self {
+            Key::Text(text) => text.len(),
+            Key::Bytes(bytes) => bytes.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Text keys are written as they are, and binary keys in hexadecimal prefixed with `0x`.
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Key::Text(text) => write!(f, "{}", text),
+            Key::Bytes(bytes) => {
+                write!(f, "0x")?;
+                bytes.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+        }
+    }
+}
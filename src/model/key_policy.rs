@@ -0,0 +1,153 @@
+use crate::model::key::Key;
+use crate::model::partition_key::PartitionKey;
+use crate::model::sort_key::SortKey;
+use std::fmt::{Display, Formatter};
+use unicode_normalization::UnicodeNormalization;
+
+/// Rules the partition and sort keys of requests must follow.
+#[derive(Clone, Copy, Debug)]
+pub struct KeyPolicy {
+    /// Maximum length of partition keys, in bytes (of UTF-8 for text keys).
+    pub max_partition_key_length: usize,
+    /// Maximum length of sort keys, in bytes (of UTF-8 for text keys).
+    pub max_sort_key_length: usize,
+    /// Whether text keys are normalized to NFC, so that canonically equivalent keys are the same key.
+    pub normalize: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidKeyReason {
+    Empty,
+    TooLong {
+        length: usize,
+        max_length: usize,
+    },
+    /// `position` is the index of the character in the key.
+    ControlCharacter {
+        position: usize,
+    },
+    /// An element of a tuple key has no value.
+    MissingTupleElement {
+        index: usize,
+    },
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self {
+            max_partition_key_length: 1024,
+            max_sort_key_length: 1024,
+            normalize: false,
+        }
+    }
+}
+
+impl KeyPolicy {
+    pub fn partition_key(&self, key: Key) -> Result<PartitionKey, InvalidKeyReason> {
+        self.validate(key, self.max_partition_key_length).map(PartitionKey)
+    }
+
+    pub fn sort_key(&self, key: Key) -> Result<SortKey, InvalidKeyReason> {
+        self.validate(key, self.max_sort_key_length).map(SortKey)
+    }
+
+    /// Binary keys may hold any byte, so only their length is checked.
+    ///
+    /// NOTE: The length is checked after normalization, since it is the normalized key that is stored.
+    fn validate(&self, key: Key, max_length: usize) -> Result<Key, InvalidKeyReason> {
+        let key = match key {
+            Key::Text(value) if self.normalize => Key::Text(value.nfc().collect()),
+            key => key,
+        };
+
+        if key.is_empty() {
+            return Err(InvalidKeyReason::Empty);
+        }
+        if let Key::Text(value) = &key {
+            if let Some(position) = value.chars().position(char::is_control) {
+                return Err(InvalidKeyReason::ControlCharacter { position });
+            }
+        }
+        if key.len() > max_length {
+            return Err(InvalidKeyReason::TooLong { length: key.len(), max_length });
+        }
+        Ok(key)
+    }
+}
+
+impl Display for InvalidKeyReason {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            InvalidKeyReason::Empty => write!(f, "key is empty"),
+            InvalidKeyReason::TooLong { length, max_length } => write!(f, "key is {} bytes long, the maximum is {}", length, max_length),
+            InvalidKeyReason::ControlCharacter { position } => write!(f, "key contains a control character at position {}", position),
+            InvalidKeyReason::MissingTupleElement { index } => write!(f, "tuple element {} has no value", index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn policy(normalize: bool) -> KeyPolicy {
+        KeyPolicy {
+            max_partition_key_length: 64,
+            max_sort_key_length: 32,
+            normalize,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn accepted_keys_follow_the_rules(value in any::<String>(), normalize in any::<bool>()) {
+            let policy = policy(normalize);
+            if let Ok(SortKey(Key::Text(key))) = policy.sort_key(Key::Text(value)) {
+                prop_assert!(!key.is_empty());
+                prop_assert!(key.len() <= policy.max_sort_key_length);
+                prop_assert!(!key.chars().any(char::is_control));
+            }
+        }
+
+        #[test]
+        fn valid_keys_are_kept_as_is(value in "[^\\p{Cc}]{1,16}") {
+            let key = policy(false).partition_key(Key::Text(value.clone()));
+            prop_assert_eq!(key.map(|key| key.0), Ok(Key::Text(value)));
+        }
+
+        #[test]
+        fn binary_keys_only_have_a_length_limit(value in proptest::collection::vec(any::<u8>(), 0..100), normalize in any::<bool>()) {
+            let length = value.len();
+            let expected = match length {
+                0 => Err(InvalidKeyReason::Empty),
+                1..=64 => Ok(Key::Bytes(value.clone())),
+                _ => Err(InvalidKeyReason::TooLong { length, max_length: 64 }),
+            };
+            let key = policy(normalize).partition_key(Key::Bytes(value));
+            prop_assert_eq!(key.map(|key| key.0), expected);
+        }
+
+        #[test]
+        fn long_keys_are_rejected(value in "[a-z]{33,100}") {
+            let length = value.len();
+            let reason = policy(false).sort_key(Key::Text(value)).err();
+            prop_assert_eq!(reason, Some(InvalidKeyReason::TooLong { length, max_length: 32 }));
+        }
+
+        #[test]
+        fn control_characters_are_rejected(prefix in "[a-z]{0,8}", control in "\\p{Cc}", suffix in "[a-z]{0,8}") {
+            let value = format!("{}{}{}", prefix, control, suffix);
+            let reason = policy(false).partition_key(Key::Text(value)).err();
+            prop_assert_eq!(reason, Some(InvalidKeyReason::ControlCharacter { position: prefix.chars().count() }));
+        }
+
+        #[test]
+        fn equivalent_keys_are_normalized_to_the_same_key(value in "[^\\p{Cc}]{1,8}") {
+            let policy = policy(true);
+            let composed = policy.partition_key(Key::Text(value.nfc().collect())).map(|key| key.0);
+            let decomposed = policy.partition_key(Key::Text(value.nfd().collect())).map(|key| key.0);
+            prop_assert_eq!(composed, decomposed);
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use crate::model::item::Item;
+use std::fmt::{Display, Formatter};
+use uuid::Uuid;
+
+/// An item whose value is split in chunks, as written by `PutLarge`.
+#[derive(Clone, Debug)]
+pub struct LargeItem {
+    /// The item, whose value is empty when it was written in chunks.
+    pub item: Item,
+    /// Number of chunks of the value, which are read one at a time.
+    pub chunk_count: u64,
+}
+
+/// Identifies the chunks received by a `PutLarge`, until they replace the value of the item.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct UploadId(pub String);
+
+impl UploadId {
+    pub fn generate() -> Self {
+        UploadId(Uuid::new_v4().to_string())
+    }
+}
+
+impl Display for UploadId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
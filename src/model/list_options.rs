@@ -0,0 +1,18 @@
+use crate::model::projection::Projection;
+use crate::model::snapshot::SnapshotId;
+use crate::model::sort_key::SortKey;
+
+/// How the items of a `List` are returned.
+#[derive(Clone, Debug)]
+pub struct ListOptions {
+    /// Continues a previous listing after this sort key, in the order of the listing.
+    pub start_after: Option<SortKey>,
+    /// Lists the items in descending order of their sort keys.
+    pub reverse: bool,
+    pub projection: Projection,
+    /// Lists the deleted items as well, with their `deleted_at` set.
+    pub include_deleted: bool,
+    pub page_size: usize,
+    /// Lists the items as of the snapshot instead of their latest state.
+    pub snapshot_id: Option<SnapshotId>,
+}
@@ -1,7 +1,26 @@
+pub mod key;
+pub mod partition;
 pub mod partition_key;
 pub mod sort_key;
+pub mod sort_key_range;
+pub mod tuple;
+pub mod key_policy;
 pub mod item;
+pub mod get_options;
+pub mod list_options;
+pub mod history_retention;
+pub mod snapshot;
+pub mod delete_batch;
+pub mod projection;
+pub mod large_item;
 pub mod write_condition;
+pub mod write_mode;
 pub mod task;
 pub mod set_value;
+pub mod idempotency_key;
+pub mod request_context;
+pub mod identity;
+pub mod permission;
 
+pub mod counter;
+pub mod patch;
@@ -0,0 +1,9 @@
+use crate::model::partition_key::PartitionKey;
+
+/// A partition exists as long as it holds items.
+#[derive(Clone, Debug)]
+pub struct Partition {
+    pub partition_key: PartitionKey,
+    /// Number of items of the partition, when they were counted.
+    pub item_count: Option<u64>,
+}
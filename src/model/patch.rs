@@ -0,0 +1,22 @@
+use serde_json::Value;
+
+/// Change applied to a JSON document by the `Update` RPC.
+#[derive(Clone, Debug)]
+pub enum Patch {
+    /// RFC 7396 JSON merge patch.
+    Merge(Value),
+    /// RFC 6902 JSON Patch.
+    Json(json_patch::Patch),
+}
+
+impl Patch {
+    pub fn apply(&self, document: &mut Value) -> Result<(), json_patch::PatchError> {
+        match self {
+            Patch::Merge(patch) => {
+                json_patch::merge(document, patch);
+                Ok(())
+            }
+            Patch::Json(patch) => json_patch::patch(document, patch),
+        }
+    }
+}
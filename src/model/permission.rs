@@ -0,0 +1,41 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+    /// Grants every other permission.
+    Admin,
+}
+
+impl Permission {
+    pub fn grants(&self, permission: Permission) -> bool {
+        *self == Permission::Admin || *self == permission
+    }
+}
+
+impl TryFrom<&str> for Permission {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "read" => Ok(Permission::Read),
+            "write" => Ok(Permission::Write),
+            "delete" => Ok(Permission::Delete),
+            "admin" => Ok(Permission::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::Read => write!(f, "read"),
+            Permission::Write => write!(f, "write"),
+            Permission::Delete => write!(f, "delete"),
+            Permission::Admin => write!(f, "admin"),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+/// Parts of the items returned by `Get` and `List`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Projection {
+    /// The whole item.
+    #[default]
+    Full,
+    /// The keys and metadata of the item, with an empty value, so that its value is not read.
+    KeysOnly,
+}
+
+impl Projection {
+    pub fn is_keys_only(&self) -> bool {
+        *self == Projection::KeysOnly
+    }
+}
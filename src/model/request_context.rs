@@ -0,0 +1,29 @@
+use crate::model::identity::Identity;
+use std::fmt::{Display, Formatter};
+use std::time::Instant;
+use uuid::Uuid;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        RequestId(Uuid::new_v4().to_string())
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Information about the RPC that originated a task, carried from the gRPC layer to the processor.
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    pub request_id: RequestId,
+    /// `None` when the client is not authenticated.
+    pub identity: Option<Identity>,
+    /// Time after which the client no longer waits for the result, from the `grpc-timeout` header.
+    pub deadline: Option<Instant>,
+}
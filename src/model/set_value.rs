@@ -1,8 +1,11 @@
 use crate::model::sort_key::SortKey;
 use crate::model::write_condition::WriteCondition;
+use crate::model::write_mode::WriteMode;
 
 pub struct SetValue {
     pub sort_key: SortKey,
+    pub sort_key_is_tuple: bool,
     pub write_condition: WriteCondition,
+    pub write_mode: WriteMode,
     pub value: Vec<u8>,
 }
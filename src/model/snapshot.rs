@@ -0,0 +1,27 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Identifies a snapshot opened by `BeginSnapshot`, which reads can be pinned to.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SnapshotId(pub String);
+
+impl SnapshotId {
+    pub fn generate() -> Self {
+        SnapshotId(Uuid::new_v4().to_string())
+    }
+}
+
+impl Display for SnapshotId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A snapshot of the database, kept open until it is ended or goes unused for longer than its lease.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub snapshot_id: SnapshotId,
+    /// Extended by every read made in the snapshot.
+    pub lease: Duration,
+}
@@ -1,31 +1,5 @@
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
-
-pub struct SortKey(pub String);
-
-pub struct InvalidSortKey;
-
-
-impl TryFrom<&str> for SortKey {
-    type Error = InvalidSortKey;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.is_empty() {
-            Err(InvalidSortKey)
-        } else {
-            Ok(SortKey(value.to_string()))
-        }
-    }
-}
-
-impl TryFrom<String> for SortKey {
-    type Error = InvalidSortKey;
-
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        if value.is_empty() {
-            Err(InvalidSortKey)
-        } else {
-            Ok(SortKey(value))
-        }
-    }
-}
+use crate::model::key::Key;
 
+/// Built by `KeyPolicy::sort_key`, which validates it.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct SortKey(pub Key);
@@ -0,0 +1,10 @@
+use crate::model::sort_key::SortKey;
+use std::collections::Bound;
+
+/// Sort keys selected by a `List`.
+#[derive(Clone, Debug)]
+pub enum SortKeyRange {
+    Bounds(Bound<SortKey>, Bound<SortKey>),
+    /// The sort keys of the same kind (text or binary) starting with the prefix, compared byte-wise.
+    Prefix(SortKey),
+}
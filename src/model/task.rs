@@ -1,26 +1,236 @@
-use std::collections::Bound;
+use std::time::Instant;
+use time::OffsetDateTime;
 use tokio::sync::mpsc::Sender;
+use tracing::Span;
 use crate::error::db::DatabaseError;
+use crate::model::counter::Counter;
+use crate::model::delete_batch::DeleteBatch;
+use crate::model::get_options::GetOptions;
+use crate::model::history_retention::HistoryRetention;
+use crate::model::idempotency_key::IdempotencyKey;
 use crate::model::item::Item;
+use crate::model::list_options::ListOptions;
+use crate::model::large_item::{LargeItem, UploadId};
+use crate::model::partition::Partition;
 use crate::model::partition_key::PartitionKey;
+use crate::model::patch::Patch;
+use crate::model::request_context::RequestContext;
 use crate::model::set_value::SetValue;
+use crate::model::snapshot::{Snapshot, SnapshotId};
 use crate::model::sort_key::SortKey;
+use crate::model::sort_key_range::SortKeyRange;
+use crate::model::write_condition::WriteCondition;
 
-pub enum Task {
+pub struct Task {
+    pub context: RequestContext,
+    // NOTE: The span of the repository call, used as the parent of the processor span so that the
+    // work done by the processor is linked to the originating RPC.
+    pub span: Span,
+    /// When the task was sent to the processor, used to measure how long it waited in the queue.
+    pub enqueued_at: Instant,
+    pub operation: Operation,
+}
+
+pub enum Operation {
     Get {
         partition_key: PartitionKey,
         sort_key: SortKey,
+        options: GetOptions,
         sender: Sender<Result<Option<Item>, DatabaseError>>,
     },
     Set {
         partition_key: PartitionKey,
         set_value: Vec<SetValue>,
-        sender: Sender<Result<bool, DatabaseError>>,
+        idempotency_key: Option<IdempotencyKey>,
+        sender: Sender<Result<(), DatabaseError>>,
+    },
+    Increment {
+        partition_key: PartitionKey,
+        sort_key: SortKey,
+        sort_key_is_tuple: bool,
+        delta: i64,
+        initial_value: i64,
+        sender: Sender<Result<Counter, DatabaseError>>,
+    },
+    Update {
+        partition_key: PartitionKey,
+        sort_key: SortKey,
+        write_condition: WriteCondition,
+        patch: Patch,
+        /// Maximum size of the patched value.
+        max_value_size: usize,
+        sender: Sender<Result<Item, DatabaseError>>,
     },
     List {
         partition_key: PartitionKey,
-        range: (Bound<SortKey>, Bound<SortKey>),
+        range: SortKeyRange,
+        options: ListOptions,
+        sender: Sender<Result<Vec<Item>, DatabaseError>>,
+    },
+    Count {
+        partition_key: PartitionKey,
+        range: SortKeyRange,
+        max_count: Option<u64>,
+        sender: Sender<Result<u64, DatabaseError>>,
+    },
+    DeleteRange {
+        partition_key: PartitionKey,
+        range: SortKeyRange,
+        start_after: Option<SortKey>,
+        batch_size: usize,
+        sender: Sender<Result<DeleteBatch, DatabaseError>>,
+    },
+    Undelete {
+        partition_key: PartitionKey,
+        sort_key: SortKey,
+        sender: Sender<Result<Item, DatabaseError>>,
+    },
+    PurgeTombstones {
+        deleted_before: OffsetDateTime,
+        batch_size: usize,
+        sender: Sender<Result<u64, DatabaseError>>,
+    },
+    GetHistory {
+        partition_key: PartitionKey,
+        sort_key: SortKey,
+        before_version: Option<u64>,
         page_size: usize,
         sender: Sender<Result<Vec<Item>, DatabaseError>>,
     },
+    SetHistoryRetention {
+        partition_key: PartitionKey,
+        retention: Option<HistoryRetention>,
+        sender: Sender<Result<(), DatabaseError>>,
+    },
+    PurgeHistory {
+        now: OffsetDateTime,
+        batch_size: usize,
+        sender: Sender<Result<u64, DatabaseError>>,
+    },
+    BeginSnapshot {
+        sender: Sender<Result<Snapshot, DatabaseError>>,
+    },
+    EndSnapshot {
+        snapshot_id: SnapshotId,
+        sender: Sender<Result<(), DatabaseError>>,
+    },
+    ListPartitions {
+        prefix: Option<PartitionKey>,
+        start_after: Option<PartitionKey>,
+        include_item_count: bool,
+        page_size: usize,
+        sender: Sender<Result<Vec<Partition>, DatabaseError>>,
+    },
+    AppendLargeChunk {
+        upload_id: UploadId,
+        chunk_index: u64,
+        chunk: Vec<u8>,
+        sender: Sender<Result<(), DatabaseError>>,
+    },
+    PutLarge {
+        partition_key: PartitionKey,
+        sort_key: SortKey,
+        sort_key_is_tuple: bool,
+        write_condition: WriteCondition,
+        upload_id: UploadId,
+        sender: Sender<Result<u64, DatabaseError>>,
+    },
+    AbortLargeUpload {
+        upload_id: UploadId,
+        sender: Sender<Result<(), DatabaseError>>,
+    },
+    GetLarge {
+        partition_key: PartitionKey,
+        sort_key: SortKey,
+        sender: Sender<Result<Option<LargeItem>, DatabaseError>>,
+    },
+    GetLargeChunk {
+        partition_key: PartitionKey,
+        sort_key: SortKey,
+        version: u64,
+        chunk_index: u64,
+        sender: Sender<Result<Vec<u8>, DatabaseError>>,
+    },
+}
+
+impl Operation {
+    /// Name of the operation, used to label metrics and spans.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Get { .. } => "get",
+            Operation::Set { .. } => "set",
+            Operation::Increment { .. } => "increment",
+            Operation::Update { .. } => "update",
+            Operation::List { .. } => "list",
+            Operation::Count { .. } => "count",
+            Operation::DeleteRange { .. } => "delete_range",
+            Operation::Undelete { .. } => "undelete",
+            Operation::PurgeTombstones { .. } => "purge_tombstones",
+            Operation::GetHistory { .. } => "get_history",
+            Operation::SetHistoryRetention { .. } => "set_history_retention",
+            Operation::PurgeHistory { .. } => "purge_history",
+            Operation::BeginSnapshot { .. } => "begin_snapshot",
+            Operation::EndSnapshot { .. } => "end_snapshot",
+            Operation::ListPartitions { .. } => "list_partitions",
+            Operation::AppendLargeChunk { .. } => "append_large_chunk",
+            Operation::PutLarge { .. } => "put_large",
+            Operation::AbortLargeUpload { .. } => "abort_large_upload",
+            Operation::GetLarge { .. } => "get_large",
+            Operation::GetLargeChunk { .. } => "get_large_chunk",
+        }
+    }
+
+    /// Whether the caller stopped waiting for the result, e.g. because the RPC was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        match self {
+            Operation::Get { sender, .. } => sender.is_closed(),
+            Operation::Set { sender, .. } => sender.is_closed(),
+            Operation::Increment { sender, .. } => sender.is_closed(),
+            Operation::Update { sender, .. } => sender.is_closed(),
+            Operation::List { sender, .. } => sender.is_closed(),
+            Operation::Count { sender, .. } => sender.is_closed(),
+            Operation::DeleteRange { sender, .. } => sender.is_closed(),
+            Operation::Undelete { sender, .. } => sender.is_closed(),
+            Operation::PurgeTombstones { sender, .. } => sender.is_closed(),
+            Operation::GetHistory { sender, .. } => sender.is_closed(),
+            Operation::SetHistoryRetention { sender, .. } => sender.is_closed(),
+            Operation::PurgeHistory { sender, .. } => sender.is_closed(),
+            Operation::BeginSnapshot { sender } => sender.is_closed(),
+            Operation::EndSnapshot { sender, .. } => sender.is_closed(),
+            Operation::ListPartitions { sender, .. } => sender.is_closed(),
+            Operation::AppendLargeChunk { sender, .. } => sender.is_closed(),
+            Operation::PutLarge { sender, .. } => sender.is_closed(),
+            Operation::AbortLargeUpload { sender, .. } => sender.is_closed(),
+            Operation::GetLarge { sender, .. } => sender.is_closed(),
+            Operation::GetLargeChunk { sender, .. } => sender.is_closed(),
+        }
+    }
+
+    /// Replies with an error without executing the operation.
+    pub fn reject(self, error: DatabaseError) {
+        // NOTE: The caller may have given up on the reply already, in which case there is no one
+        // to notify.
+        let _ = match self {
+            Operation::Get { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::Set { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::Increment { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::Update { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::List { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::Count { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::DeleteRange { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::Undelete { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::PurgeTombstones { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::GetHistory { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::SetHistoryRetention { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::PurgeHistory { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::BeginSnapshot { sender } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::EndSnapshot { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::ListPartitions { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::AppendLargeChunk { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::PutLarge { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::AbortLargeUpload { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::GetLarge { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+            Operation::GetLargeChunk { sender, .. } => sender.blocking_send(Err(error)).is_ok(),
+        };
+    }
 }
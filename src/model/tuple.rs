@@ -0,0 +1,165 @@
+//! Order-preserving encoding of tuples into binary keys, compatible with the tuple layer of
+//! FoundationDB for the supported types: comparing two encoded tuples byte-wise gives the same result as
+//! comparing the tuples element by element, and a tuple sorts right before the tuples it is a prefix of.
+
+const BYTES_CODE: u8 = 0x01;
+const STRING_CODE: u8 = 0x02;
+const INTEGER_ZERO_CODE: u8 = 0x14;
+const FALSE_CODE: u8 = 0x26;
+const TRUE_CODE: u8 = 0x27;
+
+/// NOTE: The variants are declared in the order of their type codes, so that the derived ordering is
+/// the one of their encoding.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum TupleElement {
+    Bytes(Vec<u8>),
+    String(String),
+    Integer(i64),
+    Bool(bool),
+}
+
+pub fn encode(elements: &[TupleElement]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for element in elements {
+        match element {
+            TupleElement::Bytes(bytes) => encode_bytes(&mut encoded, BYTES_CODE, bytes),
+            TupleElement::String(string) => encode_bytes(&mut encoded, STRING_CODE, string.as_bytes()),
+            TupleElement::Integer(integer) => encode_integer(&mut encoded, *integer),
+            TupleElement::Bool(false) => encoded.push(FALSE_CODE),
+            TupleElement::Bool(true) => encoded.push(TRUE_CODE),
+        }
+    }
+    encoded
+}
+
+/// Decodes a tuple, or returns `None` if the bytes are not the encoding of a tuple.
+///
+/// NOTE: Only canonical encodings are accepted, so that decoding and encoding again gives back the same
+/// key.
+pub fn decode(encoded: &[u8]) -> Option<Vec<TupleElement>> {
+    let mut elements = Vec::new();
+    let mut rest = encoded;
+    while let Some((&code, tail)) = rest.split_first() {
+        let (element, tail) = match code {
+            BYTES_CODE => decode_bytes(tail).map(|(bytes, tail)| (TupleElement::Bytes(bytes), tail))?,
+            STRING_CODE => {
+                let (bytes, tail) = decode_bytes(tail)?;
+                (TupleElement::String(String::from_utf8(bytes).ok()?), tail)
+            }
+            0x0c..=0x1c => decode_integer(code, tail)?,
+            FALSE_CODE => (TupleElement::Bool(false), tail),
+            TRUE_CODE => (TupleElement::Bool(true), tail),
+            _ => return None,
+        };
+        elements.push(element);
+        rest = tail;
+    }
+
+    (encode(&elements) == encoded).then_some(elements)
+}
+
+/// NOTE: Null bytes are escaped as `00 ff`, so that the terminating null byte sorts first.
+fn encode_bytes(encoded: &mut Vec<u8>, code: u8, bytes: &[u8]) {
+    encoded.push(code);
+    for &byte in bytes {
+        encoded.push(byte);
+        if byte == 0x00 {
+            encoded.push(0xff);
+        }
+    }
+    encoded.push(0x00);
+}
+
+fn decode_bytes(encoded: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut bytes = Vec::new();
+    let mut index = 0;
+    loop {
+        match (encoded.get(index)?, encoded.get(index + 1)) {
+            (0x00, Some(0xff)) => {
+                bytes.push(0x00);
+                index += 2;
+            }
+            (0x00, _) => return Some((bytes, &encoded[index + 1..])),
+            (&byte, _) => {
+                bytes.push(byte);
+                index += 1;
+            }
+        }
+    }
+}
+
+/// Integers are written big-endian on as few bytes as possible, the type code giving the number of bytes
+/// and the sign. Negative integers are written in one's complement, so that they sort in order.
+fn encode_integer(encoded: &mut Vec<u8>, integer: i64) {
+    let magnitude = integer.unsigned_abs();
+    let length = (u64::BITS - magnitude.leading_zeros()).div_ceil(8) as usize;
+    let bytes = if integer >= 0 {
+        encoded.push(INTEGER_ZERO_CODE + length as u8);
+        magnitude.to_be_bytes()
+    } else {
+        encoded.push(INTEGER_ZERO_CODE - length as u8);
+        (!magnitude).to_be_bytes()
+    };
+    encoded.extend_from_slice(&bytes[8 - length..]);
+}
+
+fn decode_integer(code: u8, encoded: &[u8]) -> Option<(TupleElement, &[u8])> {
+    let negative = code < INTEGER_ZERO_CODE;
+    let length = code.abs_diff(INTEGER_ZERO_CODE) as usize;
+    if encoded.len() < length {
+        return None;
+    }
+    let (bytes, tail) = encoded.split_at(length);
+
+    let fill = if negative { 0xff } else { 0x00 };
+    let mut buffer = [fill; 8];
+    buffer[8 - length..].copy_from_slice(bytes);
+    let value = u64::from_be_bytes(buffer);
+
+    let integer = if negative {
+        // NOTE: `!value` is the magnitude, which is at most 2^63 for an i64.
+        0i64.checked_sub_unsigned(!value)?
+    } else {
+        i64::try_from(value).ok()?
+    };
+    Some((TupleElement::Integer(integer), tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn element() -> impl Strategy<Value=TupleElement> {
+        prop_oneof![
+            proptest::collection::vec(any::<u8>(), 0..8).prop_map(TupleElement::Bytes),
+            ".{0,8}".prop_map(TupleElement::String),
+            any::<i64>().prop_map(TupleElement::Integer),
+            prop_oneof![Just(-1i64), Just(0), Just(1), Just(255), Just(256), Just(i64::MIN), Just(i64::MAX)].prop_map(TupleElement::Integer),
+            any::<bool>().prop_map(TupleElement::Bool),
+        ]
+    }
+
+    fn tuple() -> impl Strategy<Value=Vec<TupleElement>> {
+        proptest::collection::vec(element(), 0..4)
+    }
+
+    proptest! {
+        #[test]
+        fn tuples_are_decoded_back(tuple in tuple()) {
+            prop_assert_eq!(decode(&encode(&tuple)), Some(tuple));
+        }
+
+        #[test]
+        fn encoding_preserves_the_order(a in tuple(), b in tuple()) {
+            prop_assert_eq!(encode(&a).cmp(&encode(&b)), a.cmp(&b));
+        }
+
+        #[test]
+        fn decoded_bytes_are_canonical(bytes in proptest::collection::vec(any::<u8>(), 0..16)) {
+            if let Some(tuple) = decode(&bytes) {
+                prop_assert_eq!(encode(&tuple), bytes);
+            }
+        }
+    }
+}
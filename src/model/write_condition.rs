@@ -1,4 +1,50 @@
+use crate::model::item::Item;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Existence {
+    Exists,
+    NotExists,
+}
+
+/// Preconditions of a write, which must all hold for the write to be applied.
 #[derive(Debug, Clone, Default)]
 pub struct WriteCondition {
     pub version_equals: Option<u64>, // 0 means not exists
+    pub existence: Option<Existence>,
+    pub version_less_than: Option<u64>,
+    pub updated_before: Option<OffsetDateTime>,
+    /// SHA-256 of the current value.
+    pub value_hash_equals: Option<[u8; 32]>,
+}
+
+impl WriteCondition {
+    /// Whether the condition holds for the current item, `None` if it does not exist.
+    ///
+    /// NOTE: A missing item has version 0, and fails the conditions on its timestamps and value.
+    pub fn is_met(&self, item: Option<&Item>) -> bool {
+        let version = item.map_or(0, |item| item.version);
+
+        self.version_equals.is_none_or(|version_equals| version == version_equals)
+            && self.version_less_than.is_none_or(|version_less_than| version < version_less_than)
+            && self.existence.is_none_or(|existence| match existence {
+                Existence::Exists => item.is_some(),
+                Existence::NotExists => item.is_none(),
+            })
+            && self.updated_before.is_none_or(|updated_before| {
+                item.is_some_and(|item| item.updated_at < updated_before)
+            })
+            && self.value_hash_equals.is_none_or(|value_hash| {
+                item.is_some_and(|item| Sha256::digest(&item.value).as_slice() == value_hash)
+            })
+    }
+
+    /// Whether the condition only constrains the version, which `SET_ITEM_STATEMENT` checks itself.
+    pub fn is_version_only(&self) -> bool {
+        self.existence.is_none()
+            && self.version_less_than.is_none()
+            && self.updated_before.is_none()
+            && self.value_hash_equals.is_none()
+    }
 }
@@ -0,0 +1,38 @@
+use crate::error::db::PreconditionFailure;
+use crate::model::item::Item;
+
+/// How a write treats an existing item.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    #[default]
+    Upsert,
+    InsertOnly,
+    UpdateOnly,
+    /// Updates the item only if its version is `version_equals`.
+    ReplaceIfVersion(u64),
+}
+
+impl WriteMode {
+    /// Checks the mode against the current item, `None` if it does not exist.
+    pub fn check(&self, item: Option<&Item>) -> Result<(), PreconditionFailure> {
+        match (self, item) {
+            (WriteMode::InsertOnly, Some(_)) => Err(PreconditionFailure::ItemAlreadyExists),
+            (WriteMode::UpdateOnly | WriteMode::ReplaceIfVersion(_), None) => Err(PreconditionFailure::ItemDoesNotExist),
+            (WriteMode::ReplaceIfVersion(expected), Some(item)) if item.version != *expected => Err(PreconditionFailure::VersionMismatch {
+                expected: *expected,
+                actual: item.version,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the item must not exist yet.
+    pub fn is_insert_only(&self) -> bool {
+        matches!(self, WriteMode::InsertOnly)
+    }
+
+    /// Whether the item must exist already.
+    pub fn is_update_only(&self) -> bool {
+        matches!(self, WriteMode::UpdateOnly | WriteMode::ReplaceIfVersion(_))
+    }
+}
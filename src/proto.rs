@@ -1,6 +1,12 @@
 
 pub mod parapluie {
-    pub(crate) const FILE_DESCRIPTOR_SET: &[u8] =
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
         tonic::include_file_descriptor_set!("service_descriptor");
     tonic::include_proto!("parapluie");
 }
+
+pub mod google {
+    pub mod rpc {
+        tonic::include_proto!("google.rpc");
+    }
+}
@@ -0,0 +1,26 @@
+use crate::error::counter::CounterError;
+use crate::model::counter;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::Connection;
+
+/// Registers the SQL functions used by the queries. They must be registered on every connection.
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+    // NOTE: counter_add(value, delta) adds delta to an encoded counter.
+    conn.create_scalar_function(
+        "counter_add",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let value: Vec<u8> = ctx.get(0)?;
+            let delta: i64 = ctx.get(1)?;
+
+            let value = counter::decode(&value)
+                .ok_or(CounterError::NotAnInteger)
+                .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
+            value.checked_add(delta)
+                .map(counter::encode)
+                .ok_or(CounterError::Overflow)
+                .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))
+        },
+    )
+}
@@ -1,7 +1,14 @@
+#[allow(clippy::module_inception)]
 mod repository;
 mod query_shim;
 mod processor;
+mod sweeper;
+mod snapshots;
+pub mod schema;
+pub mod functions;
 
 pub use repository::Repository;
 pub use processor::Processor;
+pub use sweeper::sweep;
+pub use snapshots::Snapshots;
 
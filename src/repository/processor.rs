@@ -1,97 +1,571 @@
 use crate::repository::query_shim::SQLiteQueryShim;
-use rusqlite::Connection;
-use std::collections::Bound;
+use crate::repository::snapshots::Snapshots;
+use rusqlite::{Connection, Transaction};
+use std::time::{Duration, Instant};
 use time::OffsetDateTime;
 use tokio::sync::mpsc::{Receiver, Sender};
-use crate::error::db::{DatabaseError};
+use crate::error::db::{DatabaseError, PreconditionFailure};
 use crate::error::app::AppError;
+use crate::metrics::Metrics;
+use crate::model::counter::Counter;
+use crate::model::delete_batch::DeleteBatch;
+use crate::model::get_options::GetOptions;
+use crate::model::history_retention::HistoryRetention;
+use crate::model::idempotency_key::IdempotencyKey;
+use crate::model::identity::Identity;
 use crate::model::item::Item;
+use crate::model::list_options::ListOptions;
+use crate::model::large_item::{LargeItem, UploadId};
+use crate::model::partition::Partition;
 use crate::model::partition_key::PartitionKey;
+use crate::model::patch::Patch;
 use crate::model::set_value::SetValue;
+use crate::model::snapshot::{Snapshot, SnapshotId};
 use crate::model::sort_key::SortKey;
-use crate::model::task::Task;
+use crate::model::sort_key_range::SortKeyRange;
+use crate::model::task::{Operation, Task};
+use crate::model::write_condition::WriteCondition;
+use crate::model::write_mode::WriteMode;
+use tracing::{debug, info, info_span, warn};
 
 pub struct Processor {
     conn: Connection,
+    snapshots: Snapshots,
     receiver: Receiver<Task>,
+    metrics: Metrics,
+    max_queue_wait: Option<Duration>,
+    idempotency_window: Duration,
 }
 
 impl Processor {
-    pub fn new(conn: Connection, receiver: Receiver<Task>) -> Self {
+    pub fn new(conn: Connection, snapshots: Snapshots, receiver: Receiver<Task>, metrics: Metrics, max_queue_wait: Option<Duration>, idempotency_window: Duration) -> Self {
         Self {
             conn,
+            snapshots,
             receiver,
+            metrics,
+            max_queue_wait,
+            idempotency_window,
         }
     }
 
     pub fn blocking_process_tasks(mut self) -> Result<(), AppError> {
-        while let Some(request) = self.receiver.blocking_recv() {
-            self.process_task(request)?;
+        while let Some(task) = self.receiver.blocking_recv() {
+            self.metrics.task_dequeued();
+            // NOTE: Leases are only checked when a task arrives, which the sweeper guarantees at
+            // least every minute.
+            self.expire_snapshots();
+
+            let operation_name = task.operation.name();
+            let span = info_span!(
+                parent: &task.span,
+                "processor.task",
+                operation = operation_name,
+                request_id = %task.context.request_id,
+            );
+            let _entered = span.enter();
+
+            if let Err(e) = self.check_admission(&task) {
+                warn!(error = %e, "rejecting task without executing it");
+                task.operation.reject(e);
+                continue;
+            }
+
+            let start = Instant::now();
+            self.process_operation(task.operation, task.context.identity.as_ref());
+            self.metrics.observe_task(operation_name, start.elapsed());
         }
         // NOTE: The channel is closed when all the senders are dropped, which happens when the gRPC
         // server is stopped.
-        println!("Processor task finished");
+        info!("processor task finished");
         Ok(())
     }
-    fn process_task(&mut self, request: Task) -> Result<(), AppError> {
-        match request {
-            Task::Get { partition_key, sort_key, sender } => {
-                let result = self.process_get(partition_key, sort_key);
-                reply(sender, result)?;
+
+    /// Checks whether the task is still worth executing.
+    ///
+    /// NOTE: Tasks are only checked before they start: once a write is executed, it is committed even
+    /// if the caller gives up while it runs.
+    fn check_admission(&self, task: &Task) -> Result<(), DatabaseError> {
+        if task.operation.is_cancelled() {
+            return Err(DatabaseError::Cancelled);
+        }
+
+        let now = Instant::now();
+        if task.context.deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(DatabaseError::DeadlineExceeded);
+        }
+
+        let waited = now.duration_since(task.enqueued_at);
+        if self.max_queue_wait.is_some_and(|max_queue_wait| waited > max_queue_wait) {
+            return Err(DatabaseError::QueueWaitExceeded(waited));
+        }
+
+        Ok(())
+    }
+
+    fn expire_snapshots(&mut self) {
+        let expired = self.snapshots.expire(Instant::now());
+        if expired > 0 {
+            info!(expired, "released snapshots whose lease expired");
+            self.metrics.set_open_snapshots(self.snapshots.open_count());
+        }
+    }
+
+    /// Returns the connection reads are made on: the one of the snapshot if set, extending its lease,
+    /// or the main connection to read the latest state.
+    fn read_connection(&mut self, snapshot_id: Option<&SnapshotId>, identity: Option<&Identity>) -> Result<&Connection, DatabaseError> {
+        match snapshot_id {
+            Some(snapshot_id) => self.snapshots.connection(snapshot_id, identity),
+            None => Ok(&self.conn),
+        }
+    }
+
+    /// `identity` is the one of the client that sent the operation, which owns the snapshots it opens.
+    fn process_operation(&mut self, operation: Operation, identity: Option<&Identity>) {
+        match operation {
+            Operation::Get { partition_key, sort_key, options, sender } => {
+                let result = self.process_get(partition_key, sort_key, options, identity);
+                reply(sender, result);
+            }
+            Operation::Set { partition_key, set_value, idempotency_key, sender } => {
+                let result = self.process_set(partition_key, set_value, idempotency_key, identity);
+                reply(sender, result);
             }
-            Task::Set { partition_key, set_value, sender } => {
-                let result = self.process_set(partition_key, set_value);
-                reply(sender, result)?;
+            Operation::Increment { partition_key, sort_key, sort_key_is_tuple, delta, initial_value, sender } => {
+                let result = self.process_increment(partition_key, sort_key, sort_key_is_tuple, delta, initial_value);
+                reply(sender, result);
             }
-            Task::List { partition_key, range, page_size, sender } => {
-                let result = self.process_list(partition_key, range, page_size);
-                reply(sender, result)?;
+            Operation::Update { partition_key, sort_key, write_condition, patch, max_value_size, sender } => {
+                let result = self.process_update(partition_key, sort_key, write_condition, patch, max_value_size);
+                reply(sender, result);
+            }
+            Operation::List { partition_key, range, options, sender } => {
+                let result = self.process_list(partition_key, range, options, identity);
+                reply(sender, result);
+            }
+            Operation::Count { partition_key, range, max_count, sender } => {
+                let result = self.process_count(partition_key, range, max_count);
+                reply(sender, result);
+            }
+            Operation::DeleteRange { partition_key, range, start_after, batch_size, sender } => {
+                let result = self.process_delete_range(partition_key, range, start_after, batch_size);
+                reply(sender, result);
+            }
+            Operation::Undelete { partition_key, sort_key, sender } => {
+                let result = self.process_undelete(partition_key, sort_key);
+                reply(sender, result);
+            }
+            Operation::PurgeTombstones { deleted_before, batch_size, sender } => {
+                let result = self.process_purge_tombstones(deleted_before, batch_size);
+                reply(sender, result);
+            }
+            Operation::GetHistory { partition_key, sort_key, before_version, page_size, sender } => {
+                let result = self.process_get_history(partition_key, sort_key, before_version, page_size);
+                reply(sender, result);
+            }
+            Operation::SetHistoryRetention { partition_key, retention, sender } => {
+                let result = self.process_set_history_retention(partition_key, retention);
+                reply(sender, result);
+            }
+            Operation::PurgeHistory { now, batch_size, sender } => {
+                let result = self.process_purge_history(now, batch_size);
+                reply(sender, result);
+            }
+            Operation::BeginSnapshot { sender } => {
+                let result = self.process_begin_snapshot(identity);
+                reply(sender, result);
+            }
+            Operation::EndSnapshot { snapshot_id, sender } => {
+                let result = self.process_end_snapshot(snapshot_id, identity);
+                reply(sender, result);
+            }
+            Operation::ListPartitions { prefix, start_after, include_item_count, page_size, sender } => {
+                let result = self.process_list_partitions(prefix, start_after, include_item_count, page_size);
+                reply(sender, result);
+            }
+            Operation::AppendLargeChunk { upload_id, chunk_index, chunk, sender } => {
+                let result = self.process_append_large_chunk(upload_id, chunk_index, chunk);
+                reply(sender, result);
+            }
+            Operation::PutLarge { partition_key, sort_key, sort_key_is_tuple, write_condition, upload_id, sender } => {
+                let result = self.process_put_large(partition_key, sort_key, sort_key_is_tuple, write_condition, upload_id);
+                reply(sender, result);
+            }
+            Operation::AbortLargeUpload { upload_id, sender } => {
+                let result = self.process_abort_large_upload(upload_id);
+                reply(sender, result);
+            }
+            Operation::GetLarge { partition_key, sort_key, sender } => {
+                let result = self.process_get_large(partition_key, sort_key);
+                reply(sender, result);
+            }
+            Operation::GetLargeChunk { partition_key, sort_key, version, chunk_index, sender } => {
+                let result = self.process_get_large_chunk(partition_key, sort_key, version, chunk_index);
+                reply(sender, result);
             }
         }
-        Ok(())
     }
 
-    fn process_get(&self, partition_key: PartitionKey, sort_key: SortKey) -> Result<Option<Item>, DatabaseError> {
-        let conn = &self.conn;
+    fn process_get(&mut self, partition_key: PartitionKey, sort_key: SortKey, options: GetOptions, identity: Option<&Identity>) -> Result<Option<Item>, DatabaseError> {
+        let conn = self.read_connection(options.snapshot_id.as_ref(), identity)?;
         let store = SQLiteQueryShim::new(&conn);
-        let item = store.get(&partition_key, &sort_key)?;
+        let item = store.get(&partition_key, &sort_key, options)?;
         Ok(item)
     }
 
-    fn process_set(&mut self, partition_key: PartitionKey, set_values: Vec<SetValue>) -> Result<bool, DatabaseError> {
-        let txn = (&mut self.conn).transaction()?;
+    /// Writes all the values, or none of them if a write condition or write mode is not met.
+    ///
+    /// NOTE: Successful writes are recorded with the idempotency key in the same transaction, so that a
+    /// retry either replays the result or finds that nothing was written. A key recorded for another
+    /// request, or another caller, is rejected rather than replayed.
+    fn process_set(&mut self, partition_key: PartitionKey, set_values: Vec<SetValue>, idempotency_key: Option<IdempotencyKey>, identity: Option<&Identity>) -> Result<(), DatabaseError> {
+        let now = OffsetDateTime::now_utc();
+        let expired_before = now - self.idempotency_window;
+        let txn = self.conn.transaction()?;
+
+        if let Some(idempotency_key) = &idempotency_key {
+            let store = SQLiteQueryShim::new(&txn);
+            store.delete_expired_idempotency_keys(expired_before)
+                .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+            let matched = store.match_idempotency_key(&partition_key, idempotency_key, identity)
+                .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+            match matched {
+                Some(true) => {
+                    txn.commit().inspect_err(|_| self.metrics.transaction_rolled_back())?;
+                    self.metrics.transaction_committed();
+                    debug!("replaying the result recorded for the idempotency key");
+                    return Ok(());
+                }
+                Some(false) => {
+                    self.metrics.transaction_rolled_back();
+                    return Err(DatabaseError::IdempotencyKeyReused);
+                }
+                None => {}
+            }
+        }
+
+        if let Err(e) = write_set_values(&txn, &partition_key, set_values, now) {
+            self.metrics.transaction_rolled_back();
+            if let DatabaseError::PreconditionFailed { .. } = e {
+                self.metrics.conditional_write_failed();
+            }
+            return Err(e);
+        }
+
+        if let Some(idempotency_key) = &idempotency_key {
+            SQLiteQueryShim::new(&txn).set_idempotency_key(&partition_key, idempotency_key, identity, now)
+                .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        }
+        txn.commit().inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        self.metrics.transaction_committed();
+        Ok(())
+    }
+
+    fn process_increment(&mut self, partition_key: PartitionKey, sort_key: SortKey, sort_key_is_tuple: bool, delta: i64, initial_value: i64) -> Result<Counter, DatabaseError> {
+        let txn = self.conn.transaction()?;
         let store = SQLiteQueryShim::new(&txn);
+
+        let now = OffsetDateTime::now_utc();
+        let counter = store.increment(&partition_key, &sort_key, sort_key_is_tuple, now, delta, initial_value)
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        txn.commit()?;
+        self.metrics.transaction_committed();
+
+        Ok(counter)
+    }
+
+    fn process_update(&mut self, partition_key: PartitionKey, sort_key: SortKey, write_condition: WriteCondition, patch: Patch, max_value_size: usize) -> Result<Item, DatabaseError> {
         let now = OffsetDateTime::now_utc();
+        let txn = self.conn.transaction()?;
 
-        for v in set_values {
-            let updated = store.set(partition_key.clone(), v.sort_key, now, now, v.write_condition.version_equals, v.value)?;
-            if !updated {
-                txn.rollback()?;
-                return Ok(false);
+        let item = match update_item(&txn, partition_key, sort_key, write_condition, patch, max_value_size, now) {
+            Ok(item) => item,
+            Err(e) => {
+                self.metrics.transaction_rolled_back();
+                if let DatabaseError::PreconditionFailed { .. } = e {
+                    self.metrics.conditional_write_failed();
+                }
+                return Err(e);
             }
+        };
+        txn.commit()?;
+        self.metrics.transaction_committed();
+
+        Ok(item)
+    }
+
+    /// Replaces the item with an empty value, and stores the chunks of its value aside.
+    fn process_append_large_chunk(&mut self, upload_id: UploadId, chunk_index: u64, chunk: Vec<u8>) -> Result<(), DatabaseError> {
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        store.append_large_value_upload_chunk(&upload_id, chunk_index, &chunk)
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        txn.commit()?;
+        self.metrics.transaction_committed();
+        Ok(())
+    }
+
+    /// Replaces the item with the chunks of the upload, which are only visible once the whole value is
+    /// written.
+    fn process_put_large(&mut self, partition_key: PartitionKey, sort_key: SortKey, sort_key_is_tuple: bool, write_condition: WriteCondition, upload_id: UploadId) -> Result<u64, DatabaseError> {
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        let item = store.get(&partition_key, &sort_key, GetOptions::default())
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        if !write_condition.is_met(item.as_ref()) {
+            self.metrics.transaction_rolled_back();
+            self.metrics.conditional_write_failed();
+            return Err(DatabaseError::PreconditionFailed { index: None, failure: PreconditionFailure::ConditionNotMet });
         }
 
+        let now = OffsetDateTime::now_utc();
+        // NOTE: The item cannot change within the transaction, so the write always succeeds. Its version
+        // is the one returned by the write, which follows the version of a tombstone it replaces.
+        let set_value = SetValue {
+            sort_key: sort_key.clone(),
+            sort_key_is_tuple,
+            write_condition: WriteCondition { version_equals: Some(item.map_or(0, |item| item.version)), ..WriteCondition::default() },
+            write_mode: WriteMode::Upsert,
+            value: Vec::new(),
+        };
+        let version = store.set(&partition_key, set_value, now)
+            .map_err(DatabaseError::from)
+            .and_then(|version| version.ok_or(DatabaseError::PreconditionFailed { index: None, failure: PreconditionFailure::ConditionNotMet }))
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        store.set_large_value_from_upload(&partition_key, &sort_key, &upload_id)
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        txn.commit()?;
+        self.metrics.transaction_committed();
+
+        Ok(version)
+    }
+
+    fn process_abort_large_upload(&mut self, upload_id: UploadId) -> Result<(), DatabaseError> {
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        store.delete_large_value_upload(&upload_id)
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
         txn.commit()?;
-        Ok(true)
+        self.metrics.transaction_committed();
+        Ok(())
     }
 
-    fn process_list(&self, partition_key: PartitionKey, range: (Bound<SortKey>, Bound<SortKey>), page_size: usize) -> Result<Vec<Item>, DatabaseError> {
+    /// Reads the item and the number of chunks of its value. A value written by `Set` has no chunks, and
+    /// is kept in the item.
+    fn process_get_large(&mut self, partition_key: PartitionKey, sort_key: SortKey) -> Result<Option<LargeItem>, DatabaseError> {
+        // NOTE: The item and its chunks are counted in the same transaction, so that they are consistent.
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        let Some(item) = store.get(&partition_key, &sort_key, GetOptions::default())? else {
+            return Ok(None);
+        };
+        let chunk_count = match item.large_value_size {
+            Some(_) => store.count_large_value_chunks(&partition_key, &sort_key)?,
+            None => 0,
+        };
+        txn.commit()?;
+
+        Ok(Some(LargeItem { item, chunk_count }))
+    }
+
+    fn process_get_large_chunk(&self, partition_key: PartitionKey, sort_key: SortKey, version: u64, chunk_index: u64) -> Result<Vec<u8>, DatabaseError> {
         let conn = &self.conn;
         let store = SQLiteQueryShim::new(&conn);
+        let chunk = store.get_large_value_chunk(&partition_key, &sort_key, version, chunk_index)?;
+        chunk.ok_or(DatabaseError::LargeValueChanged)
+    }
+
+    fn process_list(&mut self, partition_key: PartitionKey, range: SortKeyRange, options: ListOptions, identity: Option<&Identity>) -> Result<Vec<Item>, DatabaseError> {
+        let conn = self.read_connection(options.snapshot_id.as_ref(), identity)?;
+        let store = SQLiteQueryShim::new(&conn);
         let items = store
             .list(
                 partition_key,
                 range,
-                page_size,
+                options,
             )?;
         Ok(items)
     }
+
+    fn process_count(&self, partition_key: PartitionKey, range: SortKeyRange, max_count: Option<u64>) -> Result<u64, DatabaseError> {
+        let conn = &self.conn;
+        let store = SQLiteQueryShim::new(&conn);
+        let count = store.count(&partition_key, range, max_count)?;
+        Ok(count)
+    }
+
+    fn process_delete_range(&mut self, partition_key: PartitionKey, range: SortKeyRange, start_after: Option<SortKey>, batch_size: usize) -> Result<DeleteBatch, DatabaseError> {
+        let now = OffsetDateTime::now_utc();
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        let sort_keys = store.delete_range(&partition_key, range, start_after, now, batch_size)
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        txn.commit()?;
+        self.metrics.transaction_committed();
+
+        Ok(DeleteBatch {
+            deleted_count: sort_keys.len() as u64,
+            last_sort_key: sort_keys.into_iter().max(),
+        })
+    }
+
+    fn process_undelete(&mut self, partition_key: PartitionKey, sort_key: SortKey) -> Result<Item, DatabaseError> {
+        let now = OffsetDateTime::now_utc();
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        let item = store.undelete(&partition_key, &sort_key, now)
+            .map_err(DatabaseError::from)
+            .and_then(|undeleted| if undeleted {
+                store.get(&partition_key, &sort_key, GetOptions::default())?.ok_or(DatabaseError::NotFound)
+            } else {
+                Err(DatabaseError::NotFound)
+            })
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        txn.commit()?;
+        self.metrics.transaction_committed();
+
+        Ok(item)
+    }
+
+    /// Purges a batch of tombstones, with their large values, in a transaction.
+    fn process_purge_tombstones(&mut self, deleted_before: OffsetDateTime, batch_size: usize) -> Result<u64, DatabaseError> {
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        let purged = store.purge_tombstones(deleted_before, batch_size)
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        txn.commit()?;
+        self.metrics.transaction_committed();
+
+        Ok(purged)
+    }
+
+    fn process_get_history(&self, partition_key: PartitionKey, sort_key: SortKey, before_version: Option<u64>, page_size: usize) -> Result<Vec<Item>, DatabaseError> {
+        let conn = &self.conn;
+        let store = SQLiteQueryShim::new(&conn);
+        let items = store.get_history(&partition_key, &sort_key, before_version, page_size)?;
+        Ok(items)
+    }
+
+    fn process_set_history_retention(&mut self, partition_key: PartitionKey, retention: Option<HistoryRetention>) -> Result<(), DatabaseError> {
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        store.set_history_retention(&partition_key, retention)
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        txn.commit()?;
+        self.metrics.transaction_committed();
+        Ok(())
+    }
+
+    fn process_purge_history(&mut self, now: OffsetDateTime, batch_size: usize) -> Result<u64, DatabaseError> {
+        let txn = self.conn.transaction()?;
+        let store = SQLiteQueryShim::new(&txn);
+
+        let purged = store.purge_history(now, batch_size)
+            .inspect_err(|_| self.metrics.transaction_rolled_back())?;
+        txn.commit()?;
+        self.metrics.transaction_committed();
+
+        Ok(purged)
+    }
+
+    fn process_begin_snapshot(&mut self, identity: Option<&Identity>) -> Result<Snapshot, DatabaseError> {
+        let snapshot = self.snapshots.begin(identity)?;
+        self.metrics.set_open_snapshots(self.snapshots.open_count());
+        Ok(snapshot)
+    }
+
+    fn process_end_snapshot(&mut self, snapshot_id: SnapshotId, identity: Option<&Identity>) -> Result<(), DatabaseError> {
+        self.snapshots.end(&snapshot_id, identity)?;
+        self.metrics.set_open_snapshots(self.snapshots.open_count());
+        Ok(())
+    }
+
+    fn process_list_partitions(&self, prefix: Option<PartitionKey>, start_after: Option<PartitionKey>, include_item_count: bool, page_size: usize) -> Result<Vec<Partition>, DatabaseError> {
+        let conn = &self.conn;
+        let store = SQLiteQueryShim::new(&conn);
+        let partitions = store.list_partitions(prefix, start_after, include_item_count, page_size)?;
+        Ok(partitions)
+    }
+}
+/// Writes the values, failing on the first one whose write condition or write mode is not met.
+fn write_set_values(txn: &Transaction, partition_key: &PartitionKey, set_values: Vec<SetValue>, now: OffsetDateTime) -> Result<(), DatabaseError> {
+    let store = SQLiteQueryShim::new(txn);
+
+    for (index, v) in set_values.into_iter().enumerate() {
+        let failed = |failure| DatabaseError::PreconditionFailed { index: Some(index), failure };
+
+        // NOTE: The write mode and the conditions other than on the version are checked against the
+        // current item, so that the reason of a conflict can be reported.
+        if v.write_mode != WriteMode::Upsert || !v.write_condition.is_version_only() {
+            let item = store.get(partition_key, &v.sort_key, GetOptions::default())?;
+            v.write_mode.check(item.as_ref()).map_err(failed)?;
+            if !v.write_condition.is_met(item.as_ref()) {
+                return Err(failed(PreconditionFailure::ConditionNotMet));
+            }
+        }
+
+        let version = store.set(partition_key, v, now)?;
+        if version.is_none() {
+            return Err(failed(PreconditionFailure::ConditionNotMet));
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the patch to the item, and returns the updated item.
+fn update_item(txn: &Transaction, partition_key: PartitionKey, sort_key: SortKey, write_condition: WriteCondition, patch: Patch, max_value_size: usize, now: OffsetDateTime) -> Result<Item, DatabaseError> {
+    let store = SQLiteQueryShim::new(txn);
+
+    let item = store.get(&partition_key, &sort_key, GetOptions::default())?.ok_or(DatabaseError::NotFound)?;
+    if item.large_value_size.is_some() {
+        return Err(DatabaseError::LargeValue);
+    }
+    if !write_condition.is_met(Some(&item)) {
+        return Err(DatabaseError::PreconditionFailed { index: None, failure: PreconditionFailure::ConditionNotMet });
+    }
+
+    let mut document: serde_json::Value = serde_json::from_slice(&item.value).map_err(DatabaseError::InvalidJson)?;
+    patch.apply(&mut document).map_err(DatabaseError::PatchFailed)?;
+    let value = document.to_string().into_bytes();
+    if value.len() > max_value_size {
+        return Err(DatabaseError::ValueTooLarge { size: value.len(), max_size: max_value_size });
+    }
+
+    // NOTE: The item cannot change within the transaction, so the write always succeeds. The patch
+    // keeps the sort key as it was written, whichever way the caller named it.
+    let set_value = SetValue {
+        sort_key,
+        sort_key_is_tuple: item.sort_key_is_tuple,
+        write_condition: WriteCondition { version_equals: Some(item.version), ..WriteCondition::default() },
+        write_mode: WriteMode::UpdateOnly,
+        value: value.clone(),
+    };
+    store.set(&partition_key, set_value, now)?;
+
+    Ok(Item {
+        updated_at: now,
+        version: item.version + 1,
+        value,
+        ..item
+    })
 }
-fn reply<T>(sender: Sender<Result<T, DatabaseError>>, result: Result<T, DatabaseError>) -> Result<(), AppError>
+
+fn reply<T>(sender: Sender<Result<T, DatabaseError>>, result: Result<T, DatabaseError>)
 where
     T: Send + 'static,
 {
-    sender.blocking_send(result)?;
-    Ok(())
+    // NOTE: The caller stops waiting when the RPC is cancelled or its deadline expires, which must not
+    // stop the processor.
+    if sender.blocking_send(result).is_err() {
+        debug!("the caller stopped waiting for the result");
+    }
 }
-
@@ -1,63 +1,332 @@
-use rusqlite::named_params;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Type, ValueRef};
+use rusqlite::{named_params, params, OptionalExtension, ToSql};
 use std::collections::Bound;
 use std::ops::Deref;
+use std::time::Duration;
 use time::OffsetDateTime;
+use crate::model::counter;
+use crate::model::counter::Counter;
+use crate::model::get_options::GetOptions;
+use crate::model::history_retention::HistoryRetention;
+use crate::model::idempotency_key::IdempotencyKey;
+use crate::model::identity::Identity;
 use crate::model::item::Item;
+use crate::model::key::Key;
+use crate::model::large_item::UploadId;
+use crate::model::list_options::ListOptions;
+use crate::model::partition::Partition;
 use crate::model::partition_key::PartitionKey;
+use crate::model::set_value::SetValue;
 use crate::model::sort_key::SortKey;
+use crate::model::sort_key_range::SortKeyRange;
 
+// NOTE: The value is only read by the branch of the CASE that is taken, so that a keys-only read does not
+// load its BLOB.
 const GET_ITEM_STATEMENT: &str = "
-    SELECT created_at, updated_at, version, value
+    SELECT created_at, updated_at, version, CASE WHEN ?3 THEN x'' ELSE value END, large_value.size, deleted_at, sort_key_is_tuple
     FROM item
-    WHERE partition_key = ?1 AND sort_key = ?2";
+    LEFT JOIN large_value USING (partition_key, sort_key)
+    WHERE partition_key = ?1 AND sort_key = ?2
+    AND (?4 OR deleted_at IS NULL)
+    AND (?5 IS NULL OR version = ?5)";
+
+// NOTE: The chunks of large values are not kept in the history, only their size.
+const GET_HISTORY_ITEM_STATEMENT: &str = "
+    SELECT created_at, updated_at, version, CASE WHEN ?3 THEN x'' ELSE value END, large_value_size, deleted_at, sort_key_is_tuple
+    FROM item_history
+    WHERE partition_key = ?1 AND sort_key = ?2
+    AND (?4 OR deleted_at IS NULL)
+    AND version = ?5";
+
+const GET_HISTORY_QUERY: &str = "
+    SELECT created_at, updated_at, version, value, large_value_size, deleted_at, sort_key_is_tuple
+    FROM item_history
+    WHERE partition_key = :partition_key AND sort_key = :sort_key
+    AND (:before_version IS NULL OR version < :before_version)
+    ORDER BY version DESC
+    LIMIT :page_size";
 
+// NOTE: A tombstone is a missing item for the write mode and the version condition, but the version of
+// the item written over it follows the one of the tombstone, so that versions never go back. The item is
+// then created anew.
 const SET_ITEM_STATEMENT: &str = "
     WITH previous_row AS (
-        SELECT version
+        SELECT version, deleted_at
         FROM item
         WHERE partition_key = :partition_key AND sort_key = :sort_key
     ),
+    live_row AS (
+        SELECT version
+        FROM previous_row
+        WHERE deleted_at IS NULL
+    ),
     can_insert AS (
         SELECT
             CASE
-                WHEN NOT EXISTS (SELECT 1 FROM previous_row) AND (
+                WHEN NOT EXISTS (SELECT 1 FROM live_row) AND NOT :update_only AND (
                     :previous_version IS NULL
                     OR :previous_version = 0
                 ) THEN 1
-                WHEN EXISTS (SELECT 1 FROM previous_row) AND (
+                WHEN EXISTS (SELECT 1 FROM live_row) AND NOT :insert_only AND (
                     :previous_version IS NULL
-                    OR (SELECT version FROM previous_row) = :previous_version
+                    OR (SELECT version FROM live_row) = :previous_version
                 ) THEN 1
                 ELSE 0
             END AS allowed
     )
-    INSERT INTO item (partition_key, sort_key, created_at, updated_at, version, value)
+    INSERT INTO item (partition_key, sort_key, created_at, updated_at, version, value, sort_key_is_tuple)
     SELECT
         :partition_key,
         :sort_key,
         :created_at,
         :updated_at,
         COALESCE((SELECT version FROM previous_row), 0) + 1,
-        :value
+        :value,
+        :sort_key_is_tuple
     FROM can_insert
     WHERE allowed = 1
     ON CONFLICT(partition_key, sort_key)
     DO UPDATE SET
+        created_at = CASE WHEN deleted_at IS NULL THEN created_at ELSE excluded.created_at END,
         updated_at = excluded.updated_at,
         version = excluded.version,
-        value = excluded.value;";
+        value = excluded.value,
+        deleted_at = NULL,
+        sort_key_is_tuple = excluded.sort_key_is_tuple
+    RETURNING version";
 
-const LIST_QUERY: &str = "
-    SELECT sort_key, created_at, updated_at, version, value
-    FROM item
+// NOTE: A missing counter, or a deleted one, is created with the initial value, to which the delta is
+// added.
+const INCREMENT_STATEMENT: &str = "
+    WITH previous_row AS (
+        SELECT version, value, deleted_at
+        FROM item
+        WHERE partition_key = :partition_key AND sort_key = :sort_key
+    )
+    INSERT INTO item (partition_key, sort_key, created_at, updated_at, version, value, sort_key_is_tuple)
+    SELECT
+        :partition_key,
+        :sort_key,
+        :created_at,
+        :updated_at,
+        COALESCE((SELECT version FROM previous_row), 0) + 1,
+        counter_add(COALESCE((SELECT value FROM previous_row WHERE deleted_at IS NULL), :initial_value), :delta),
+        :sort_key_is_tuple
+    WHERE true
+    ON CONFLICT(partition_key, sort_key)
+    DO UPDATE SET
+        created_at = CASE WHEN deleted_at IS NULL THEN created_at ELSE excluded.created_at END,
+        updated_at = excluded.updated_at,
+        version = excluded.version,
+        value = excluded.value,
+        deleted_at = NULL,
+        sort_key_is_tuple = excluded.sort_key_is_tuple
+    RETURNING value, version";
+
+const UNDELETE_STATEMENT: &str = "
+    UPDATE item
+    SET version = version + 1, updated_at = ?3, deleted_at = NULL
+    WHERE partition_key = ?1 AND sort_key = ?2 AND deleted_at IS NOT NULL";
+
+/// Conditions selecting the items of a partition whose sort key is within the bounds, set by
+/// `SortKeyBounds`.
+macro_rules! sort_key_conditions {
+    () => {
+        "
     WHERE partition_key = :partition_key
     AND (:gt_sort_key IS NULL OR sort_key > :gt_sort_key)
     AND (:ge_sort_key IS NULL OR sort_key >= :ge_sort_key)
     AND (:lt_sort_key IS NULL OR sort_key < :lt_sort_key)
-    AND (:le_sort_key IS NULL OR sort_key <= :le_sort_key)
-    ORDER BY partition_key, sort_key ASC
-    LIMIT :page_size";
+    AND (:le_sort_key IS NULL OR sort_key <= :le_sort_key)"
+    };
+}
+
+// NOTE: `:after_sort_key` and `:before_sort_key` continue a previous listing, in ascending and
+// descending order respectively.
+macro_rules! list_query {
+    ($order:literal) => {
+        concat!("
+    SELECT sort_key, created_at, updated_at, version, CASE WHEN :keys_only THEN x'' ELSE value END, large_value.size, deleted_at, sort_key_is_tuple
+    FROM item
+    LEFT JOIN large_value USING (partition_key, sort_key)", sort_key_conditions!(), "
+    AND (:include_deleted OR deleted_at IS NULL)
+    AND (:after_sort_key IS NULL OR sort_key > :after_sort_key)
+    AND (:before_sort_key IS NULL OR sort_key < :before_sort_key)
+    ORDER BY partition_key ", $order, ", sort_key ", $order, "
+    LIMIT :page_size")
+    };
+}
+
+const LIST_QUERY: &str = list_query!("ASC");
+
+const REVERSE_LIST_QUERY: &str = list_query!("DESC");
+
+// NOTE: A negative limit means no limit.
+const COUNT_QUERY: &str = concat!("
+    SELECT COUNT(*)
+    FROM (
+        SELECT 1
+        FROM item", sort_key_conditions!(), "
+        AND deleted_at IS NULL
+        LIMIT :max_count
+    )");
+
+// NOTE: Deleted items are turned into tombstones, which keep their value until they are purged. The batch
+// is taken in the order of the sort keys, so that the next batch continues after the last deleted item.
+const DELETE_RANGE_STATEMENT: &str = concat!("
+    UPDATE item
+    SET version = version + 1, updated_at = :deleted_at, deleted_at = :deleted_at
+    WHERE rowid IN (
+        SELECT rowid
+        FROM item", sort_key_conditions!(), "
+        AND deleted_at IS NULL
+        AND (:after_sort_key IS NULL OR sort_key > :after_sort_key)
+        ORDER BY sort_key
+        LIMIT :batch_size
+    )
+    RETURNING sort_key");
+
+const PURGE_TOMBSTONES_STATEMENT: &str = "
+    DELETE FROM item
+    WHERE rowid IN (
+        SELECT rowid
+        FROM item
+        WHERE deleted_at < ?1
+        LIMIT ?2
+    )
+    RETURNING partition_key, sort_key";
+
+const DELETE_ITEM_HISTORY_STATEMENT: &str = "
+    DELETE FROM item_history
+    WHERE partition_key = ?1 AND sort_key = ?2";
+
+const SET_HISTORY_RETENTION_STATEMENT: &str = "
+    INSERT INTO history_retention (partition_key, max_versions, max_age)
+    VALUES (?1, ?2, ?3)
+    ON CONFLICT(partition_key)
+    DO UPDATE SET
+        max_versions = excluded.max_versions,
+        max_age = excluded.max_age";
+
+const DELETE_HISTORY_RETENTION_STATEMENT: &str = "
+    DELETE FROM history_retention
+    WHERE partition_key = ?1";
+
+const DELETE_PARTITION_HISTORY_STATEMENT: &str = "
+    DELETE FROM item_history
+    WHERE partition_key = ?1";
+
+const GET_HISTORY_MAX_AGES_QUERY: &str = "
+    SELECT partition_key, max_age
+    FROM history_retention
+    WHERE max_age IS NOT NULL";
+
+const PURGE_HISTORY_STATEMENT: &str = "
+    DELETE FROM item_history
+    WHERE rowid IN (
+        SELECT rowid
+        FROM item_history
+        WHERE partition_key = ?1 AND replaced_at < ?2
+        LIMIT ?3
+    )";
+
+// NOTE: The partitions are found with a skip scan of the primary key index: each step seeks the first
+// partition key after the previous one, so that the items of a partition are not read unless they are
+// counted. The partition key is NULL once there is no partition left. Tombstones are not counted, but
+// keep their partition listed until they are purged.
+macro_rules! list_partitions_query {
+    ($start_operator:literal) => {
+        concat!("
+    WITH RECURSIVE partition(partition_key) AS (
+        SELECT (
+            SELECT partition_key
+            FROM item
+            WHERE partition_key ", $start_operator, " :start_partition_key
+            ORDER BY partition_key
+            LIMIT 1
+        )
+        UNION ALL
+        SELECT (
+            SELECT item.partition_key
+            FROM item
+            WHERE item.partition_key > partition.partition_key
+            ORDER BY item.partition_key
+            LIMIT 1
+        )
+        FROM partition
+        WHERE partition.partition_key IS NOT NULL
+        AND (:end_partition_key IS NULL OR partition.partition_key < :end_partition_key)
+        LIMIT :page_size
+    )
+    SELECT
+        partition_key,
+        CASE WHEN :include_item_count THEN (SELECT COUNT(*) FROM item WHERE item.partition_key = partition.partition_key AND deleted_at IS NULL) END
+    FROM partition
+    WHERE partition_key IS NOT NULL
+    AND (:end_partition_key IS NULL OR partition_key < :end_partition_key)")
+    };
+}
+
+const LIST_PARTITIONS_QUERY: &str = list_partitions_query!(">=");
+
+const LIST_PARTITIONS_AFTER_QUERY: &str = list_partitions_query!(">");
+
+// NOTE: `IS` also matches when both identities are NULL, as they are for unauthenticated callers.
+const MATCH_IDEMPOTENCY_KEY_QUERY: &str = "
+    SELECT request_hash = ?3 AND identity IS ?4
+    FROM idempotency_key
+    WHERE partition_key = ?1 AND idempotency_key = ?2";
+
+const SET_IDEMPOTENCY_KEY_STATEMENT: &str = "
+    INSERT INTO idempotency_key (partition_key, idempotency_key, request_hash, identity, created_at)
+    VALUES (?1, ?2, ?3, ?4, ?5)";
+
+// NOTE: Timestamps are stored in UTC in a fixed format, so comparing them as text orders them
+// chronologically.
+const DELETE_EXPIRED_IDEMPOTENCY_KEYS_STATEMENT: &str = "
+    DELETE FROM idempotency_key
+    WHERE created_at < ?1";
+
+const COUNT_LARGE_VALUE_CHUNKS_QUERY: &str = "
+    SELECT count(*)
+    FROM large_value_chunk
+    WHERE partition_key = ?1 AND sort_key = ?2";
+
+// NOTE: The chunk is only returned while the item is at the version it was read at, so that the chunks of
+// a stream all belong to the same value.
+const GET_LARGE_VALUE_CHUNK_QUERY: &str = "
+    SELECT large_value_chunk.data
+    FROM large_value_chunk
+    JOIN item USING (partition_key, sort_key)
+    WHERE partition_key = ?1 AND sort_key = ?2 AND item.version = ?3 AND chunk_index = ?4";
+
+const SET_LARGE_VALUE_UPLOAD_CHUNK_STATEMENT: &str = "
+    INSERT INTO large_value_upload_chunk (upload_id, chunk_index, data)
+    VALUES (?1, ?2, ?3)";
+
+const SET_LARGE_VALUE_FROM_UPLOAD_STATEMENT: &str = "
+    INSERT INTO large_value (partition_key, sort_key, size)
+    SELECT ?1, ?2, coalesce(sum(length(data)), 0)
+    FROM large_value_upload_chunk
+    WHERE upload_id = ?3";
 
+const SET_LARGE_VALUE_CHUNKS_FROM_UPLOAD_STATEMENT: &str = "
+    INSERT INTO large_value_chunk (partition_key, sort_key, chunk_index, data)
+    SELECT ?1, ?2, chunk_index, data
+    FROM large_value_upload_chunk
+    WHERE upload_id = ?3";
+
+const DELETE_LARGE_VALUE_UPLOAD_STATEMENT: &str = "
+    DELETE FROM large_value_upload_chunk
+    WHERE upload_id = ?1";
+
+const DELETE_LARGE_VALUE_STATEMENT: &str = "
+    DELETE FROM large_value
+    WHERE partition_key = ?1 AND sort_key = ?2";
+
+const DELETE_LARGE_VALUE_CHUNKS_STATEMENT: &str = "
+    DELETE FROM large_value_chunk
+    WHERE partition_key = ?1 AND sort_key = ?2";
 
 pub struct SQLiteQueryShim<'a, T> {
     conn: &'a T,
@@ -67,14 +336,25 @@ impl<'a, T> SQLiteQueryShim<'a, T>
 where
     T: Deref<Target=rusqlite::Connection>,
 {
-    pub fn new(conn: &'a T) -> SQLiteQueryShim<T> {
+    pub fn new(conn: &'a T) -> SQLiteQueryShim<'a, T> {
         SQLiteQueryShim { conn }
     }
 
-    pub fn get(&self, partition_key: &PartitionKey, sort_key: &SortKey) -> rusqlite::Result<Option<Item>> {
-        let mut stmt = self.conn.prepare(GET_ITEM_STATEMENT)?;
+    #[tracing::instrument(name = "sql.get", skip_all)]
+    pub fn get(&self, partition_key: &PartitionKey, sort_key: &SortKey, options: GetOptions) -> rusqlite::Result<Option<Item>> {
+        let params = params![&partition_key.0, &sort_key.0, options.projection.is_keys_only(), options.include_deleted, options.version];
+        let item = self.get_with(GET_ITEM_STATEMENT, partition_key, sort_key, params)?;
+        match (item, options.version) {
+            (None, Some(_)) => self.get_with(GET_HISTORY_ITEM_STATEMENT, partition_key, sort_key, params),
+            (item, _) => Ok(item),
+        }
+    }
+
+    /// Runs a query of a single item, selecting the columns of `GET_ITEM_STATEMENT`.
+    fn get_with(&self, query: &str, partition_key: &PartitionKey, sort_key: &SortKey, params: &[&dyn ToSql]) -> rusqlite::Result<Option<Item>> {
+        let mut stmt = self.conn.prepare(query)?;
 
-        let mut rows = stmt.query([&partition_key.0, &sort_key.0])?;
+        let mut rows = stmt.query(params)?;
         let row = rows.next()?;
         match row {
             Some(row) => {
@@ -82,82 +362,620 @@ where
                 let updated_at: OffsetDateTime = row.get(1)?;
                 let version: u64 = row.get(2)?;
                 let value: Vec<u8> = row.get(3)?;
+                let large_value_size: Option<u64> = row.get(4)?;
+                let deleted_at: Option<OffsetDateTime> = row.get(5)?;
+                let sort_key_is_tuple: bool = row.get(6)?;
 
                 Ok(Some(Item {
                     partition_key: partition_key.clone(),
                     sort_key: sort_key.clone(),
+                    sort_key_is_tuple,
                     created_at,
                     updated_at,
                     version,
                     value,
+                    large_value_size,
+                    deleted_at,
                 }))
             }
             None => Ok(None),
         }
     }
 
-    pub fn set(&self, partition_key: PartitionKey, sort_key: SortKey, created_at: OffsetDateTime, updated_at: OffsetDateTime, previous_version: Option<u64>, value: Vec<u8>) -> rusqlite::Result<bool> {
+    /// `now` is the update time of the item, and its creation time if it is new. Only the version condition
+    /// of the value is checked, the other ones being checked by the caller against the current item. The
+    /// chunks of a previous large value are deleted. Returns the new version of the item, or `None` if the
+    /// write mode or the version condition is not met.
+    #[tracing::instrument(name = "sql.set", skip_all)]
+    pub fn set(&self, partition_key: &PartitionKey, set_value: SetValue, now: OffsetDateTime) -> rusqlite::Result<Option<u64>> {
         let mut stmt = self.conn.prepare(SET_ITEM_STATEMENT)?;
 
-        let result = stmt.execute(named_params! {
-            ":partition_key": partition_key.0,
-            ":sort_key": sort_key.0,
-            ":created_at": created_at,
-            ":updated_at": updated_at,
-            ":previous_version": previous_version,
-            ":value": value,
-        })?;
+        let version = stmt.query_row(named_params! {
+            ":partition_key": &partition_key.0,
+            ":sort_key": &set_value.sort_key.0,
+            ":sort_key_is_tuple": set_value.sort_key_is_tuple,
+            ":created_at": now,
+            ":updated_at": now,
+            ":previous_version": set_value.write_condition.version_equals,
+            ":insert_only": set_value.write_mode.is_insert_only(),
+            ":update_only": set_value.write_mode.is_update_only(),
+            ":value": set_value.value,
+        }, |row| row.get(0)).optional()?;
+
+        if version.is_some() {
+            self.delete_large_value(partition_key, &set_value.sort_key)?;
+        }
+        Ok(version)
+    }
 
+    /// Adds the delta to the counter, and returns its new value and version.
+    ///
+    /// NOTE: A live item with a large value is not a counter, so the large value deleted after a
+    /// successful increment can only be the one of a tombstone the counter was created over.
+    #[tracing::instrument(name = "sql.increment", skip_all)]
+    pub fn increment(&self, partition_key: &PartitionKey, sort_key: &SortKey, sort_key_is_tuple: bool, now: OffsetDateTime, delta: i64, initial_value: i64) -> rusqlite::Result<Counter> {
+        let mut stmt = self.conn.prepare(INCREMENT_STATEMENT)?;
+
+        let counter = stmt.query_row(
+            named_params! {
+                ":partition_key": partition_key.0,
+                ":sort_key": sort_key.0,
+                ":sort_key_is_tuple": sort_key_is_tuple,
+                ":created_at": now,
+                ":updated_at": now,
+                ":initial_value": counter::encode(initial_value),
+                ":delta": delta,
+            },
+            |row| {
+                let value: Vec<u8> = row.get(0)?;
+                let version: u64 = row.get(1)?;
+
+                let value = counter::decode(&value)
+                    .ok_or_else(|| rusqlite::Error::InvalidColumnType(0, "value".to_string(), Type::Blob))?;
+                Ok(Counter { value, version })
+            },
+        )?;
+
+        self.delete_large_value(partition_key, sort_key)?;
+        Ok(counter)
+    }
+
+    /// Restores a tombstone, updated at `now`, and returns whether there was one.
+    #[tracing::instrument(name = "sql.undelete", skip_all)]
+    pub fn undelete(&self, partition_key: &PartitionKey, sort_key: &SortKey, now: OffsetDateTime) -> rusqlite::Result<bool> {
+        let result = self.conn.execute(UNDELETE_STATEMENT, params![&partition_key.0, &sort_key.0, now])?;
         Ok(result == 1)
     }
 
-    pub fn list(&self, partition_key: PartitionKey, range: (Bound<SortKey>, Bound<SortKey>), page_size: usize) -> rusqlite::Result<Vec<Item>> {
-        let mut stmt = self.conn.prepare(LIST_QUERY)?;
+    /// Returns the versions of the item kept in its history, from the newest to the oldest, starting
+    /// before `before_version` when continuing a previous page.
+    #[tracing::instrument(name = "sql.get_history", skip_all)]
+    pub fn get_history(&self, partition_key: &PartitionKey, sort_key: &SortKey, before_version: Option<u64>, page_size: usize) -> rusqlite::Result<Vec<Item>> {
+        let mut stmt = self.conn.prepare(GET_HISTORY_QUERY)?;
 
-        let (gt_sort_key, ge_sort_key) = match range.0 {
-            Bound::Included(sort_key) => (None, Some(sort_key.0)),
-            Bound::Excluded(sort_key) => (Some(sort_key.0), None),
-            Bound::Unbounded => (None, None),
-        };
+        let rows = stmt.query_map(
+            named_params! {
+                ":partition_key": &partition_key.0,
+                ":sort_key": &sort_key.0,
+                ":before_version": before_version,
+                ":page_size": page_size as i64,
+            },
+            |row| {
+                Ok(Item {
+                    partition_key: partition_key.clone(),
+                    sort_key: sort_key.clone(),
+                    sort_key_is_tuple: row.get(6)?,
+                    created_at: row.get(0)?,
+                    updated_at: row.get(1)?,
+                    version: row.get(2)?,
+                    value: row.get(3)?,
+                    large_value_size: row.get(4)?,
+                    deleted_at: row.get(5)?,
+                })
+            },
+        )?;
 
-        let (lt_sort_key, le_sort_key) = match range.1 {
-            Bound::Included(sort_key) => (None, Some(sort_key.0)),
-            Bound::Excluded(sort_key) => (Some(sort_key.0), None),
-            Bound::Unbounded => (None, None),
+        let mut items = Vec::with_capacity(page_size);
+        for item in rows {
+            items.push(item?);
+        }
+        Ok(items)
+    }
+
+    /// Keeps the history of the partition with the retention, or stops keeping it and drops it when
+    /// `None`.
+    #[tracing::instrument(name = "sql.set_history_retention", skip_all)]
+    pub fn set_history_retention(&self, partition_key: &PartitionKey, retention: Option<HistoryRetention>) -> rusqlite::Result<()> {
+        match retention {
+            Some(retention) => {
+                let max_age = retention.max_age.map(|max_age| max_age.as_secs());
+                self.conn.execute(SET_HISTORY_RETENTION_STATEMENT, params![&partition_key.0, retention.max_versions, max_age])?;
+            }
+            None => {
+                self.conn.execute(DELETE_HISTORY_RETENTION_STATEMENT, [&partition_key.0])?;
+                self.conn.execute(DELETE_PARTITION_HISTORY_STATEMENT, [&partition_key.0])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Purges up to `batch_size` versions replaced longer ago than the maximum age of their partition,
+    /// and returns how many were purged.
+    #[tracing::instrument(name = "sql.purge_history", skip_all)]
+    pub fn purge_history(&self, now: OffsetDateTime, batch_size: usize) -> rusqlite::Result<u64> {
+        let mut stmt = self.conn.prepare(GET_HISTORY_MAX_AGES_QUERY)?;
+        let rows = stmt.query_map([], |row| {
+            let partition_key: Key = row.get(0)?;
+            let max_age: u64 = row.get(1)?;
+            Ok((PartitionKey(partition_key), Duration::from_secs(max_age)))
+        })?;
+        let mut max_ages = Vec::new();
+        for max_age in rows {
+            max_ages.push(max_age?);
+        }
+
+        let mut purged = 0;
+        for (partition_key, max_age) in max_ages {
+            let remaining = batch_size - purged;
+            if remaining == 0 {
+                break;
+            }
+            purged += self.conn.execute(PURGE_HISTORY_STATEMENT, params![&partition_key.0, now - max_age, remaining as i64])?;
+        }
+        Ok(purged as u64)
+    }
+
+    #[tracing::instrument(name = "sql.list", skip_all)]
+    pub fn list(&self, partition_key: PartitionKey, range: SortKeyRange, options: ListOptions) -> rusqlite::Result<Vec<Item>> {
+        let mut stmt = self.conn.prepare(if options.reverse { REVERSE_LIST_QUERY } else { LIST_QUERY })?;
+
+        let bounds = SortKeyBounds::from(range);
+        let (after_sort_key, before_sort_key) = match options.reverse {
+            false => (options.start_after.map(|sort_key| sort_key.0), None),
+            true => (None, options.start_after.map(|sort_key| sort_key.0)),
         };
 
-        let mut rows = stmt.query_map(
+        let rows = stmt.query_map(
             named_params! {
                 ":partition_key": partition_key.0,
-                ":gt_sort_key": gt_sort_key,
-                ":ge_sort_key": ge_sort_key,
-                ":lt_sort_key": lt_sort_key,
-                ":le_sort_key": le_sort_key,
-                ":page_size": page_size as i64,
+                ":gt_sort_key": bounds.gt,
+                ":ge_sort_key": bounds.ge,
+                ":lt_sort_key": bounds.lt,
+                ":le_sort_key": bounds.le,
+                ":after_sort_key": after_sort_key,
+                ":before_sort_key": before_sort_key,
+                ":keys_only": options.projection.is_keys_only(),
+                ":include_deleted": options.include_deleted,
+                ":page_size": options.page_size as i64,
             },
             |row| {
-                let sort_key: String = row.get(0)?;
+                let sort_key: Key = row.get(0)?;
                 let created_at: OffsetDateTime = row.get(1)?;
                 let updated_at: OffsetDateTime = row.get(2)?;
                 let version: u64 = row.get(3)?;
                 let value: Vec<u8> = row.get(4)?;
+                let large_value_size: Option<u64> = row.get(5)?;
+                let deleted_at: Option<OffsetDateTime> = row.get(6)?;
+                let sort_key_is_tuple: bool = row.get(7)?;
 
                 Ok(Item {
                     partition_key: partition_key.clone(),
                     sort_key: SortKey(sort_key),
+                    sort_key_is_tuple,
                     created_at,
                     updated_at,
                     version,
                     value,
+                    large_value_size,
+                    deleted_at,
                 })
             },
         )?;
 
-        let mut items = Vec::with_capacity(page_size);
-        while let Some(item) = rows.next() {
+        let mut items = Vec::with_capacity(options.page_size);
+        for item in rows {
             items.push(item?);
         }
         Ok(items)
     }
+
+    /// Counts the items of the range, up to `max_count` if set.
+    #[tracing::instrument(name = "sql.count", skip_all)]
+    pub fn count(&self, partition_key: &PartitionKey, range: SortKeyRange, max_count: Option<u64>) -> rusqlite::Result<u64> {
+        let mut stmt = self.conn.prepare(COUNT_QUERY)?;
+
+        let bounds = SortKeyBounds::from(range);
+        stmt.query_row(
+            named_params! {
+                ":partition_key": &partition_key.0,
+                ":gt_sort_key": bounds.gt,
+                ":ge_sort_key": bounds.ge,
+                ":lt_sort_key": bounds.lt,
+                ":le_sort_key": bounds.le,
+                ":max_count": max_count.map_or(-1, |max_count| max_count.min(i64::MAX as u64) as i64),
+            },
+            |row| row.get(0),
+        )
+    }
+
+    /// Deletes up to `batch_size` items of the range after `start_after`, leaving tombstones deleted at
+    /// `now`, and returns the sort keys of the deleted items, in no particular order.
+    #[tracing::instrument(name = "sql.delete_range", skip_all)]
+    pub fn delete_range(&self, partition_key: &PartitionKey, range: SortKeyRange, start_after: Option<SortKey>, now: OffsetDateTime, batch_size: usize) -> rusqlite::Result<Vec<SortKey>> {
+        let mut stmt = self.conn.prepare(DELETE_RANGE_STATEMENT)?;
+
+        let bounds = SortKeyBounds::from(range);
+        let rows = stmt.query_map(
+            named_params! {
+                ":partition_key": &partition_key.0,
+                ":gt_sort_key": bounds.gt,
+                ":ge_sort_key": bounds.ge,
+                ":lt_sort_key": bounds.lt,
+                ":le_sort_key": bounds.le,
+                ":after_sort_key": start_after.map(|sort_key| sort_key.0),
+                ":deleted_at": now,
+                ":batch_size": batch_size as i64,
+            },
+            |row| row.get(0).map(SortKey),
+        )?;
+
+        let mut sort_keys = Vec::with_capacity(batch_size);
+        for sort_key in rows {
+            sort_keys.push(sort_key?);
+        }
+        Ok(sort_keys)
+    }
+
+    /// Purges up to `batch_size` tombstones of items deleted before `deleted_before`, with their large
+    /// values and their history, and returns how many were purged.
+    #[tracing::instrument(name = "sql.purge_tombstones", skip_all)]
+    pub fn purge_tombstones(&self, deleted_before: OffsetDateTime, batch_size: usize) -> rusqlite::Result<u64> {
+        let mut stmt = self.conn.prepare(PURGE_TOMBSTONES_STATEMENT)?;
+        let rows = stmt.query_map(params![deleted_before, batch_size as i64], |row| {
+            Ok((PartitionKey(row.get(0)?), SortKey(row.get(1)?)))
+        })?;
+
+        let mut keys = Vec::with_capacity(batch_size);
+        for key in rows {
+            keys.push(key?);
+        }
+        for (partition_key, sort_key) in &keys {
+            self.delete_large_value(partition_key, sort_key)?;
+            self.conn.execute(DELETE_ITEM_HISTORY_STATEMENT, [&partition_key.0, &sort_key.0])?;
+        }
+        Ok(keys.len() as u64)
+    }
+
+    /// Lists the partitions whose key starts with the prefix, counting their items if asked to.
+    ///
+    /// NOTE: The empty text key is the smallest key, so it starts the listing when there is neither a
+    /// prefix nor a previous page.
+    #[tracing::instrument(name = "sql.list_partitions", skip_all)]
+    pub fn list_partitions(&self, prefix: Option<PartitionKey>, start_after: Option<PartitionKey>, include_item_count: bool, page_size: usize) -> rusqlite::Result<Vec<Partition>> {
+        let prefix = prefix.map(|prefix| RawKey::from(prefix.0));
+        let end = prefix.as_ref().and_then(RawKey::prefix_end);
+        let prefix = prefix.unwrap_or(RawKey::Text(Vec::new()));
+
+        let (query, start) = match start_after.map(|start_after| RawKey::from(start_after.0)) {
+            Some(start_after) if start_after >= prefix => (LIST_PARTITIONS_AFTER_QUERY, start_after),
+            _ => (LIST_PARTITIONS_QUERY, prefix),
+        };
+        let mut stmt = self.conn.prepare(query)?;
+
+        let rows = stmt.query_map(
+            named_params! {
+                ":start_partition_key": start,
+                ":end_partition_key": end,
+                ":include_item_count": include_item_count,
+                ":page_size": page_size as i64,
+            },
+            |row| {
+                let partition_key: Key = row.get(0)?;
+                let item_count: Option<u64> = row.get(1)?;
+
+                Ok(Partition {
+                    partition_key: PartitionKey(partition_key),
+                    item_count,
+                })
+            },
+        )?;
+
+        let mut partitions = Vec::with_capacity(page_size);
+        for partition in rows {
+            partitions.push(partition?);
+        }
+        Ok(partitions)
+    }
+
+    /// Returns whether the successful write recorded for the idempotency key was the same request, from
+    /// the same caller, or `None` when no write was recorded for it.
+    #[tracing::instrument(name = "sql.match_idempotency_key", skip_all)]
+    pub fn match_idempotency_key(&self, partition_key: &PartitionKey, idempotency_key: &IdempotencyKey, identity: Option<&Identity>) -> rusqlite::Result<Option<bool>> {
+        let mut stmt = self.conn.prepare(MATCH_IDEMPOTENCY_KEY_QUERY)?;
+        let identity = identity.map(Identity::to_string);
+        stmt.query_row(params![&partition_key.0, &idempotency_key.key, &idempotency_key.request_hash, identity], |row| row.get(0))
+            .optional()
+    }
+
+    #[tracing::instrument(name = "sql.set_idempotency_key", skip_all)]
+    pub fn set_idempotency_key(&self, partition_key: &PartitionKey, idempotency_key: &IdempotencyKey, identity: Option<&Identity>, created_at: OffsetDateTime) -> rusqlite::Result<()> {
+        let mut stmt = self.conn.prepare(SET_IDEMPOTENCY_KEY_STATEMENT)?;
+        let identity = identity.map(Identity::to_string);
+        stmt.execute(params![&partition_key.0, &idempotency_key.key, &idempotency_key.request_hash, identity, created_at])?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "sql.delete_expired_idempotency_keys", skip_all)]
+    pub fn delete_expired_idempotency_keys(&self, created_before: OffsetDateTime) -> rusqlite::Result<usize> {
+        let mut stmt = self.conn.prepare(DELETE_EXPIRED_IDEMPOTENCY_KEYS_STATEMENT)?;
+        stmt.execute([created_before])
+    }
+
+    #[tracing::instrument(name = "sql.count_large_value_chunks", skip_all)]
+    pub fn count_large_value_chunks(&self, partition_key: &PartitionKey, sort_key: &SortKey) -> rusqlite::Result<u64> {
+        let mut stmt = self.conn.prepare(COUNT_LARGE_VALUE_CHUNKS_QUERY)?;
+        stmt.query_row([&partition_key.0, &sort_key.0], |row| row.get(0))
+    }
+
+    /// Returns a chunk of the large value of the item, or `None` when the item is no longer at `version`.
+    #[tracing::instrument(name = "sql.get_large_value_chunk", skip_all)]
+    pub fn get_large_value_chunk(&self, partition_key: &PartitionKey, sort_key: &SortKey, version: u64, chunk_index: u64) -> rusqlite::Result<Option<Vec<u8>>> {
+        let mut stmt = self.conn.prepare(GET_LARGE_VALUE_CHUNK_QUERY)?;
+        stmt.query_row(params![&partition_key.0, &sort_key.0, version, chunk_index], |row| row.get(0))
+            .optional()
+    }
+
+    #[tracing::instrument(name = "sql.append_large_value_upload_chunk", skip_all)]
+    pub fn append_large_value_upload_chunk(&self, upload_id: &UploadId, chunk_index: u64, chunk: &[u8]) -> rusqlite::Result<()> {
+        self.conn.execute(SET_LARGE_VALUE_UPLOAD_CHUNK_STATEMENT, params![&upload_id.0, chunk_index, chunk])?;
+        Ok(())
+    }
+
+    /// Moves the chunks of the upload to the large value of an item, which must not have one yet.
+    #[tracing::instrument(name = "sql.set_large_value_from_upload", skip_all)]
+    pub fn set_large_value_from_upload(&self, partition_key: &PartitionKey, sort_key: &SortKey, upload_id: &UploadId) -> rusqlite::Result<()> {
+        let params = params![&partition_key.0, &sort_key.0, &upload_id.0];
+        self.conn.execute(SET_LARGE_VALUE_FROM_UPLOAD_STATEMENT, params)?;
+        self.conn.execute(SET_LARGE_VALUE_CHUNKS_FROM_UPLOAD_STATEMENT, params)?;
+        self.delete_large_value_upload(upload_id)
+    }
+
+    #[tracing::instrument(name = "sql.delete_large_value_upload", skip_all)]
+    pub fn delete_large_value_upload(&self, upload_id: &UploadId) -> rusqlite::Result<()> {
+        self.conn.execute(DELETE_LARGE_VALUE_UPLOAD_STATEMENT, [&upload_id.0])?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "sql.delete_large_value", skip_all)]
+    fn delete_large_value(&self, partition_key: &PartitionKey, sort_key: &SortKey) -> rusqlite::Result<()> {
+        self.conn.execute(DELETE_LARGE_VALUE_STATEMENT, [&partition_key.0, &sort_key.0])?;
+        self.conn.execute(DELETE_LARGE_VALUE_CHUNKS_STATEMENT, [&partition_key.0, &sort_key.0])?;
+        Ok(())
+    }
+}
+
+/// Text keys are stored as TEXT and binary keys as BLOB, in the same columns.
+impl ToSql for Key {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let value = match self {
+            Key::Text(text) => ValueRef::Text(text.as_bytes()),
+            Key::Bytes(bytes) => ValueRef::Blob(bytes),
+        };
+        Ok(ToSqlOutput::Borrowed(value))
+    }
+}
+
+impl FromSql for Key {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Text(_) => String::column_result(value).map(Key::Text),
+            ValueRef::Blob(bytes) => Ok(Key::Bytes(bytes.to_vec())),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// Parameters of `sort_key_conditions!`. A prefix is turned into the range from the prefix, included, to
+/// the first key greater than every key starting with it, excluded.
+struct SortKeyBounds {
+    gt: Option<RawKey>,
+    ge: Option<RawKey>,
+    lt: Option<RawKey>,
+    le: Option<RawKey>,
+}
+
+impl From<SortKeyRange> for SortKeyBounds {
+    fn from(range: SortKeyRange) -> Self {
+        let (start, end) = match range {
+            SortKeyRange::Bounds(start, end) => (start.map(|sort_key| RawKey::from(sort_key.0)), end.map(|sort_key| RawKey::from(sort_key.0))),
+            SortKeyRange::Prefix(prefix) => {
+                let prefix = RawKey::from(prefix.0);
+                let end = prefix.prefix_end();
+                (Bound::Included(prefix), end.map_or(Bound::Unbounded, Bound::Excluded))
+            }
+        };
+
+        let (gt, ge) = match start {
+            Bound::Included(sort_key) => (None, Some(sort_key)),
+            Bound::Excluded(sort_key) => (Some(sort_key), None),
+            Bound::Unbounded => (None, None),
+        };
+
+        let (lt, le) = match end {
+            Bound::Included(sort_key) => (None, Some(sort_key)),
+            Bound::Excluded(sort_key) => (Some(sort_key), None),
+            Bound::Unbounded => (None, None),
+        };
+
+        SortKeyBounds { gt, ge, lt, le }
+    }
+}
+
+/// A key compared with the keys in SQL, bound as TEXT or BLOB like the keys of its kind.
+///
+/// NOTE: Unlike a `Key`, its text may not be valid UTF-8, as is the end of a text prefix: SQLite compares
+/// text byte-wise without checking it. The variants are declared in the order SQLite sorts them, so that
+/// the derived ordering is the one of SQLite.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum RawKey {
+    Text(Vec<u8>),
+    Bytes(Vec<u8>),
+}
+
+impl RawKey {
+    /// The first key greater than every key starting with this one: the key with its last byte
+    /// incremented, once the `ff` bytes that cannot be incremented are dropped. `None` if every greater
+    /// key starts with this one.
+    fn prefix_end(&self) -> Option<RawKey> {
+        let (RawKey::Text(bytes) | RawKey::Bytes(bytes)) = self;
+        let mut end = bytes.clone();
+        while end.last() == Some(&0xff) {
+            end.pop();
+        }
+
+        match (self, end.last_mut()) {
+            (RawKey::Text(_), Some(last)) => {
+                *last += 1;
+                Some(RawKey::Text(end))
+            }
+            (RawKey::Bytes(_), Some(last)) => {
+                *last += 1;
+                Some(RawKey::Bytes(end))
+            }
+            // NOTE: Binary keys sort after text keys, so the smallest of them ends the text keys.
+            (RawKey::Text(_), None) => Some(RawKey::Bytes(Vec::new())),
+            (RawKey::Bytes(_), None) => None,
+        }
+    }
+}
+
+impl From<Key> for RawKey {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Text(text) => RawKey::Text(text.into_bytes()),
+            Key::Bytes(bytes) => RawKey::Bytes(bytes),
+        }
+    }
 }
 
+impl ToSql for RawKey {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let value = match self {
+            RawKey::Text(bytes) => ValueRef::Text(bytes),
+            RawKey::Bytes(bytes) => ValueRef::Blob(bytes),
+        };
+        Ok(ToSqlOutput::Borrowed(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::projection::Projection;
+    use crate::model::write_condition::WriteCondition;
+    use crate::model::write_mode::WriteMode;
+    use crate::repository::schema;
+    use proptest::prelude::*;
+    use rusqlite::Connection;
+
+    fn text(text: &str) -> RawKey {
+        RawKey::Text(text.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn prefix_ends_skip_trailing_ff_bytes() {
+        assert_eq!(RawKey::Bytes(vec![0x01, 0xff, 0xff]).prefix_end(), Some(RawKey::Bytes(vec![0x02])));
+        assert_eq!(RawKey::Bytes(vec![0xfe, 0xff]).prefix_end(), Some(RawKey::Bytes(vec![0xff])));
+    }
+
+    #[test]
+    fn prefix_ends_of_ff_bytes_are_unbounded() {
+        assert_eq!(RawKey::Bytes(vec![0xff, 0xff]).prefix_end(), None);
+        assert_eq!(RawKey::Bytes(Vec::new()).prefix_end(), None);
+    }
+
+    #[test]
+    fn prefix_ends_of_multi_byte_text_increment_its_last_byte() {
+        // NOTE: "é" is c3 a9, and U+10FFFF is f4 8f bf bf; the ends are not valid UTF-8.
+        assert_eq!(text("é").prefix_end(), Some(RawKey::Text(vec![0xc3, 0xaa])));
+        assert_eq!(text("a\u{10ffff}").prefix_end(), Some(RawKey::Text(vec![b'a', 0xf4, 0x8f, 0xbf, 0xc0])));
+    }
+
+    #[test]
+    fn prefix_end_of_empty_text_is_the_first_binary_key() {
+        assert_eq!(text("").prefix_end(), Some(RawKey::Bytes(Vec::new())));
+    }
+
+    fn key() -> impl Strategy<Value=Key> {
+        prop_oneof![
+            "[aé\u{7f}\u{10ffff}]{0,3}".prop_map(Key::Text),
+            proptest::collection::vec(prop_oneof![Just(0x00u8), Just(0x01), Just(0xfe), Just(0xff)], 0..4).prop_map(Key::Bytes),
+        ]
+    }
+
+    /// Lists the items of the prefix with SQLite, as `List` does.
+    fn list_prefix(keys: &[Key], prefix: &Key) -> Vec<Key> {
+        let conn = Connection::open_in_memory().unwrap();
+        schema::create(&conn).unwrap();
+        let conn = &conn;
+        let store = SQLiteQueryShim::new(&conn);
+        let partition_key = PartitionKey(Key::Text("partition".to_string()));
+        for key in keys {
+            let set_value = SetValue {
+                sort_key: SortKey(key.clone()),
+                sort_key_is_tuple: false,
+                write_condition: WriteCondition::default(),
+                write_mode: WriteMode::Upsert,
+                value: Vec::new(),
+            };
+            store.set(&partition_key, set_value, OffsetDateTime::now_utc()).unwrap();
+        }
+
+        let options = ListOptions {
+            start_after: None,
+            reverse: false,
+            projection: Projection::KeysOnly,
+            include_deleted: false,
+            page_size: keys.len() + 1,
+            snapshot_id: None,
+        };
+        store.list(partition_key, SortKeyRange::Prefix(SortKey(prefix.clone())), options)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.sort_key.0)
+            .collect()
+    }
+
+    /// The keys of the same kind as the prefix that start with it, in the order of SQLite.
+    fn filter_prefix(keys: &[Key], prefix: &Key) -> Vec<Key> {
+        let mut keys: Vec<Key> = keys.iter()
+            .filter(|key| match (key, prefix) {
+                (Key::Text(key), Key::Text(prefix)) => key.as_bytes().starts_with(prefix.as_bytes()),
+                (Key::Bytes(key), Key::Bytes(prefix)) => key.starts_with(prefix),
+                _ => false,
+            })
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    proptest! {
+        #[test]
+        fn prefixes_list_the_keys_starting_with_them(keys in proptest::collection::vec(key(), 0..16), prefix in key()) {
+            prop_assert_eq!(list_prefix(&keys, &prefix), filter_prefix(&keys, &prefix));
+        }
+
+        #[test]
+        fn prefix_ends_follow_every_key_starting_with_the_prefix(key in key(), prefix in key()) {
+            let (key, prefix) = (RawKey::from(key), RawKey::from(prefix));
+            let starts_with = match (&key, &prefix) {
+                (RawKey::Text(key), RawKey::Text(prefix)) | (RawKey::Bytes(key), RawKey::Bytes(prefix)) => key.starts_with(prefix),
+                _ => false,
+            };
+            let within = key >= prefix && prefix.prefix_end().is_none_or(|end| key < end);
+            prop_assert_eq!(within, starts_with);
+        }
+    }
+}
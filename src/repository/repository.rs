@@ -1,70 +1,377 @@
 use crate::error::db::DatabaseError;
-use crate::error::db::DatabaseError::{FailedToSendRequest, NoRemainingMessageInChannel};
+use crate::error::db::DatabaseError::{FailedToSendRequest, NoRemainingMessageInChannel, QueueFull};
+use crate::metrics::Metrics;
+use crate::model::counter::Counter;
+use crate::model::delete_batch::DeleteBatch;
+use crate::model::get_options::GetOptions;
+use crate::model::history_retention::HistoryRetention;
+use crate::model::idempotency_key::IdempotencyKey;
 use crate::model::item::Item;
+use crate::model::list_options::ListOptions;
+use crate::model::large_item::{LargeItem, UploadId};
+use crate::model::partition::Partition;
 use crate::model::partition_key::PartitionKey;
+use crate::model::patch::Patch;
+use crate::model::request_context::RequestContext;
 use crate::model::set_value::SetValue;
+use crate::model::snapshot::{Snapshot, SnapshotId};
 use crate::model::sort_key::SortKey;
-use crate::model::task::Task;
-use std::collections::Bound;
+use crate::model::sort_key_range::SortKeyRange;
+use crate::model::task::{Operation, Task};
+use crate::model::write_condition::WriteCondition;
+use std::time::Instant;
+use time::OffsetDateTime;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{info_span, Instrument, Span};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Repository {
     channel: Sender<Task>,
+    metrics: Metrics,
+    max_queue_depth: Option<usize>,
 }
 
 
 impl Repository {
-    pub async fn new(channel: Sender<Task>) -> Repository {
-        Repository { channel }
+    pub async fn new(channel: Sender<Task>, metrics: Metrics, max_queue_depth: Option<usize>) -> Repository {
+        Repository { channel, metrics, max_queue_depth }
     }
 
-    pub async fn get(&self, partition_key: PartitionKey, sort_key: SortKey) -> Result<Option<Item>, DatabaseError> {
+    pub async fn get(&self, context: RequestContext, partition_key: PartitionKey, sort_key: SortKey, options: GetOptions) -> Result<Option<Item>, DatabaseError> {
         let (sender, receiver) = mpsc::channel(1);
 
-        let request = Task::Get {
+        let operation = Operation::Get {
             partition_key,
             sort_key,
+            options,
             sender,
         };
 
-        self.call(request, receiver).await
+        self.call(context, operation, receiver).await
     }
 
-    pub async fn set(&self, partition_key: PartitionKey, set_value: Vec<SetValue>) -> Result<bool, DatabaseError> {
+    pub async fn set(&self, context: RequestContext, partition_key: PartitionKey, set_value: Vec<SetValue>, idempotency_key: Option<IdempotencyKey>) -> Result<(), DatabaseError> {
         let (sender, receiver) = mpsc::channel(1);
 
-        let request = Task::Set {
+        let operation = Operation::Set {
             partition_key,
             set_value,
+            idempotency_key,
             sender,
         };
 
-        self.call(request, receiver).await
+        self.call(context, operation, receiver).await
     }
 
-    pub async fn list(&self, partition_key: PartitionKey, range: (Bound<SortKey>, Bound<SortKey>), page_size: usize) -> Result<Vec<Item>, DatabaseError> {
+    pub async fn increment(&self, context: RequestContext, partition_key: PartitionKey, sort_key: SortKey, sort_key_is_tuple: bool, delta: i64, initial_value: i64) -> Result<Counter, DatabaseError> {
         let (sender, receiver) = mpsc::channel(1);
-        let request = Task::List {
+
+        let operation = Operation::Increment {
+            partition_key,
+            sort_key,
+            sort_key_is_tuple,
+            delta,
+            initial_value,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Returns the updated item.
+    pub async fn update(&self, context: RequestContext, partition_key: PartitionKey, sort_key: SortKey, write_condition: WriteCondition, patch: Patch, max_value_size: usize) -> Result<Item, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+
+        let operation = Operation::Update {
+            partition_key,
+            sort_key,
+            write_condition,
+            patch,
+            max_value_size,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    pub async fn list(&self, context: RequestContext, partition_key: PartitionKey, range: SortKeyRange, options: ListOptions) -> Result<Vec<Item>, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::List {
             partition_key,
             range,
+            options,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Counts the items of the range, up to `max_count` if set.
+    pub async fn count(&self, context: RequestContext, partition_key: PartitionKey, range: SortKeyRange, max_count: Option<u64>) -> Result<u64, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::Count {
+            partition_key,
+            range,
+            max_count,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Deletes up to `batch_size` items of the range, in the order of their sort keys, starting after
+    /// `start_after` when continuing a previous batch.
+    pub async fn delete_range(&self, context: RequestContext, partition_key: PartitionKey, range: SortKeyRange, start_after: Option<SortKey>, batch_size: usize) -> Result<DeleteBatch, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::DeleteRange {
+            partition_key,
+            range,
+            start_after,
+            batch_size,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Restores a deleted item, and returns it.
+    pub async fn undelete(&self, context: RequestContext, partition_key: PartitionKey, sort_key: SortKey) -> Result<Item, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::Undelete {
+            partition_key,
+            sort_key,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Purges up to `batch_size` tombstones of items deleted before `deleted_before`, and returns how many
+    /// were purged.
+    pub async fn purge_tombstones(&self, context: RequestContext, deleted_before: OffsetDateTime, batch_size: usize) -> Result<u64, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::PurgeTombstones {
+            deleted_before,
+            batch_size,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Returns the versions of the item kept in its history, from the newest to the oldest, starting
+    /// before `before_version` when continuing a previous page.
+    pub async fn get_history(&self, context: RequestContext, partition_key: PartitionKey, sort_key: SortKey, before_version: Option<u64>, page_size: usize) -> Result<Vec<Item>, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::GetHistory {
+            partition_key,
+            sort_key,
+            before_version,
             page_size,
             sender,
         };
 
-        self.call(request, receiver).await
+        self.call(context, operation, receiver).await
     }
 
-    async fn call<T>(&self, request: Task, mut receiver: Receiver<Result<T, DatabaseError>>) -> Result<T, DatabaseError> {
-        self.channel
-            .send(request)
-            .await
-            .map_err(|e| FailedToSendRequest(Box::new(e)))?;
+    /// Keeps the history of the partition with the retention, or stops keeping it when `None`.
+    pub async fn set_history_retention(&self, context: RequestContext, partition_key: PartitionKey, retention: Option<HistoryRetention>) -> Result<(), DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::SetHistoryRetention {
+            partition_key,
+            retention,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
 
-        receiver.recv()
+    /// Purges up to `batch_size` versions older than the retention of their partition, and returns how
+    /// many were purged.
+    pub async fn purge_history(&self, context: RequestContext, now: OffsetDateTime, batch_size: usize) -> Result<u64, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::PurgeHistory {
+            now,
+            batch_size,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Opens a snapshot of the database, which `get` and `list` can read through their options.
+    pub async fn begin_snapshot(&self, context: RequestContext) -> Result<Snapshot, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::BeginSnapshot {
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    pub async fn end_snapshot(&self, context: RequestContext, snapshot_id: SnapshotId) -> Result<(), DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::EndSnapshot {
+            snapshot_id,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Lists the partitions whose key starts with the prefix, in the order of their keys, starting after
+    /// `start_after` when continuing a previous listing.
+    pub async fn list_partitions(&self, context: RequestContext, prefix: Option<PartitionKey>, start_after: Option<PartitionKey>, include_item_count: bool, page_size: usize) -> Result<Vec<Partition>, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let operation = Operation::ListPartitions {
+            prefix,
+            start_after,
+            include_item_count,
+            page_size,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Stores a chunk of the upload, to be written by `put_large` once all of them are received.
+    pub async fn append_large_chunk(&self, context: RequestContext, upload_id: UploadId, chunk_index: u64, chunk: Vec<u8>) -> Result<(), DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+
+        let operation = Operation::AppendLargeChunk {
+            upload_id,
+            chunk_index,
+            chunk,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Replaces the item with a value made of the chunks of the upload, and returns its new version.
+    pub async fn put_large(&self, context: RequestContext, partition_key: PartitionKey, sort_key: SortKey, sort_key_is_tuple: bool, write_condition: WriteCondition, upload_id: UploadId) -> Result<u64, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+
+        let operation = Operation::PutLarge {
+            partition_key,
+            sort_key,
+            sort_key_is_tuple,
+            write_condition,
+            upload_id,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Drops the chunks of an upload that will not be written.
+    pub async fn abort_large_upload(&self, context: RequestContext, upload_id: UploadId) -> Result<(), DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+
+        let operation = Operation::AbortLargeUpload {
+            upload_id,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Reads the item, with the number of chunks of its value, which are read by `get_large_chunk`.
+    pub async fn get_large(&self, context: RequestContext, partition_key: PartitionKey, sort_key: SortKey) -> Result<Option<LargeItem>, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+
+        let operation = Operation::GetLarge {
+            partition_key,
+            sort_key,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Reads a chunk of the value of the item, which fails once the item is no longer at `version`.
+    pub async fn get_large_chunk(&self, context: RequestContext, partition_key: PartitionKey, sort_key: SortKey, version: u64, chunk_index: u64) -> Result<Vec<u8>, DatabaseError> {
+        let (sender, receiver) = mpsc::channel(1);
+
+        let operation = Operation::GetLargeChunk {
+            partition_key,
+            sort_key,
+            version,
+            chunk_index,
+            sender,
+        };
+
+        self.call(context, operation, receiver).await
+    }
+
+    /// Sends the operation to the processor and waits for its result.
+    async fn call<T>(&self, context: RequestContext, operation: Operation, mut receiver: Receiver<Result<T, DatabaseError>>) -> Result<T, DatabaseError> {
+        let span = info_span!("repository.call", operation = operation.name());
+
+        async move {
+            if let Some(max_queue_depth) = self.max_queue_depth {
+                let queue_depth = self.channel.max_capacity() - self.channel.capacity();
+                if queue_depth >= max_queue_depth {
+                    return Err(QueueFull);
+                }
+            }
+
+            // NOTE: The queue depth is only increased once a slot of the queue is held, so that a call
+            // dropped while waiting for one (e.g. when its RPC is cancelled) leaves it untouched. It is
+            // increased before the task is sent, so that the processor never observes a task that was not
+            // accounted for yet.
+            let permit = self.channel
+                .reserve()
+                .await
+                .map_err(|e| FailedToSendRequest(Box::new(e)))?;
+            self.metrics.task_enqueued();
+            permit.send(Task {
+                context,
+                span: Span::current(),
+                enqueued_at: Instant::now(),
+                operation,
+            });
+
+            receiver.recv()
+                .await
+                .ok_or(NoRemainingMessageInChannel)?
+        }
+            .instrument(span)
             .await
-            .ok_or(NoRemainingMessageInChannel)?
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::key::Key;
+    use crate::model::request_context::RequestId;
+    use std::future::Future;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn get(repository: &Repository) -> impl Future<Output=Result<Option<Item>, DatabaseError>> + '_ {
+        let context = RequestContext {
+            request_id: RequestId::generate(),
+            identity: None,
+            deadline: None,
+        };
+        repository.get(context, PartitionKey(Key::Text("orders".to_string())), SortKey(Key::Text("1".to_string())), GetOptions::default())
+    }
+
+    #[tokio::test]
+    async fn requests_are_rejected_once_the_queue_is_full() {
+        let metrics = Metrics::new(std::env::temp_dir().join("parapluie-unused.sqlite")).unwrap();
+        // NOTE: Nothing reads the queue, so the tasks of the first calls stay in it.
+        let (sender, _receiver) = mpsc::channel(2);
+        let repository = Repository::new(sender, metrics, Some(2)).await;
+
+        for _ in 0..2 {
+            assert!(timeout(Duration::from_millis(50), get(&repository)).await.is_err());
+        }
+
+        assert!(matches!(timeout(Duration::from_secs(1), get(&repository)).await, Ok(Err(QueueFull))));
+    }
+}
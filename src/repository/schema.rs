@@ -0,0 +1,154 @@
+use rusqlite::Connection;
+
+// NOTE: Binary keys are stored as BLOB in the key columns, which TEXT affinity leaves untouched. SQLite
+// sorts TEXT before BLOB, and compares both with memcmp. `sort_key_is_tuple` tells the binary sort keys
+// written as tuples apart from the ones written as bytes, which may be valid encodings of tuples too.
+const CREATE_ITEM_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS item (
+        partition_key TEXT NOT NULL,
+        sort_key TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        value BLOB NOT NULL,
+        deleted_at TEXT,
+        sort_key_is_tuple INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (partition_key, sort_key)
+    )";
+
+// NOTE: Columns added to the `item` table after its creation, which are added to older databases.
+const ITEM_ADDED_COLUMNS: [(&str, &str); 2] = [
+    ("deleted_at", "TEXT"),
+    ("sort_key_is_tuple", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+// NOTE: Only tombstones are indexed, so that the retention sweeper finds them without a full scan.
+const CREATE_ITEM_DELETED_AT_INDEX: &str = "
+    CREATE INDEX IF NOT EXISTS item_deleted_at ON item (deleted_at) WHERE deleted_at IS NOT NULL";
+
+// NOTE: Versions are keyed by their number, and `replaced_at` is the update time of the version that
+// replaced them, from which their age is counted.
+const CREATE_ITEM_HISTORY_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS item_history (
+        partition_key TEXT NOT NULL,
+        sort_key TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        value BLOB NOT NULL,
+        large_value_size INTEGER,
+        deleted_at TEXT,
+        sort_key_is_tuple INTEGER NOT NULL DEFAULT 0,
+        replaced_at TEXT NOT NULL,
+        PRIMARY KEY (partition_key, sort_key, version)
+    )";
+
+const CREATE_ITEM_HISTORY_INDEX: &str = "
+    CREATE INDEX IF NOT EXISTS item_history_replaced_at ON item_history (partition_key, replaced_at)";
+
+// NOTE: History is only kept for the partitions listed here. Limits are NULL when unset, and `max_age`
+// is in seconds.
+const CREATE_HISTORY_RETENTION_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS history_retention (
+        partition_key TEXT NOT NULL,
+        max_versions INTEGER,
+        max_age INTEGER,
+        PRIMARY KEY (partition_key)
+    )";
+
+// NOTE: Every write bumps the version of the item, so the trigger copies the previous row of each of
+// them, in the same transaction, whichever statement wrote it. Versions beyond `max_versions` are dropped
+// right away, while the sweeper drops the ones older than `max_age`. The large value of the previous
+// version is only replaced after the item, so its size is still there when the trigger runs.
+const CREATE_ITEM_HISTORY_TRIGGER: &str = "
+    CREATE TRIGGER IF NOT EXISTS item_history_on_update
+    AFTER UPDATE OF version ON item
+    WHEN EXISTS (SELECT 1 FROM history_retention WHERE partition_key = old.partition_key)
+    BEGIN
+        INSERT OR REPLACE INTO item_history (partition_key, sort_key, version, created_at, updated_at, value, large_value_size, deleted_at, sort_key_is_tuple, replaced_at)
+        VALUES (
+            old.partition_key, old.sort_key, old.version, old.created_at, old.updated_at, old.value,
+            (SELECT size FROM large_value WHERE partition_key = old.partition_key AND sort_key = old.sort_key),
+            old.deleted_at, old.sort_key_is_tuple, new.updated_at
+        );
+
+        DELETE FROM item_history
+        WHERE partition_key = old.partition_key AND sort_key = old.sort_key
+        AND version <= old.version - (SELECT max_versions FROM history_retention WHERE partition_key = old.partition_key);
+    END";
+
+const TABLE_HAS_COLUMN_QUERY: &str = "
+    SELECT EXISTS (SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)";
+
+// NOTE: Idempotency keys are scoped to a partition, so that clients writing to different partitions
+// cannot collide. The hash of the request and the identity of the caller tell a retry apart from
+// another write reusing the key; `identity` is NULL when the caller is not authenticated.
+const CREATE_IDEMPOTENCY_KEY_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS idempotency_key (
+        partition_key TEXT NOT NULL,
+        idempotency_key TEXT NOT NULL,
+        request_hash BLOB NOT NULL,
+        identity TEXT,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (partition_key, idempotency_key)
+    )";
+
+const CREATE_IDEMPOTENCY_KEY_INDEX: &str = "
+    CREATE INDEX IF NOT EXISTS idempotency_key_created_at ON idempotency_key (created_at)";
+
+// NOTE: The value of an item written in chunks is empty in the `item` table, and its chunks are stored
+// here. The size is kept aside so that reading the item does not have to sum the chunks.
+const CREATE_LARGE_VALUE_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS large_value (
+        partition_key TEXT NOT NULL,
+        sort_key TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        PRIMARY KEY (partition_key, sort_key)
+    )";
+
+const CREATE_LARGE_VALUE_CHUNK_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS large_value_chunk (
+        partition_key TEXT NOT NULL,
+        sort_key TEXT NOT NULL,
+        chunk_index INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        PRIMARY KEY (partition_key, sort_key, chunk_index)
+    )";
+
+// NOTE: The chunks of a `PutLarge` are written here as they are received, each by a task of its own, so
+// that the value is never held in memory whole. They are moved to `large_value_chunk` once the stream
+// ends, in the transaction that writes the item.
+const CREATE_LARGE_VALUE_UPLOAD_CHUNK_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS large_value_upload_chunk (
+        upload_id TEXT NOT NULL,
+        chunk_index INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        PRIMARY KEY (upload_id, chunk_index)
+    )";
+
+// NOTE: The streams that were writing chunks did not survive the restart, so their chunks are dropped.
+const DELETE_LARGE_VALUE_UPLOAD_CHUNKS: &str = "
+    DELETE FROM large_value_upload_chunk";
+
+/// Creates the tables used by the processor if they do not exist yet.
+pub fn create(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(CREATE_ITEM_TABLE, [])?;
+    for (column, definition) in ITEM_ADDED_COLUMNS {
+        let exists: bool = conn.query_row(TABLE_HAS_COLUMN_QUERY, ["item", column], |row| row.get(0))?;
+        if !exists {
+            conn.execute(&format!("ALTER TABLE item ADD COLUMN {} {}", column, definition), [])?;
+        }
+    }
+    conn.execute(CREATE_ITEM_DELETED_AT_INDEX, [])?;
+    conn.execute(CREATE_IDEMPOTENCY_KEY_TABLE, [])?;
+    conn.execute(CREATE_IDEMPOTENCY_KEY_INDEX, [])?;
+    conn.execute(CREATE_LARGE_VALUE_TABLE, [])?;
+    conn.execute(CREATE_LARGE_VALUE_CHUNK_TABLE, [])?;
+    conn.execute(CREATE_LARGE_VALUE_UPLOAD_CHUNK_TABLE, [])?;
+    conn.execute(DELETE_LARGE_VALUE_UPLOAD_CHUNKS, [])?;
+    conn.execute(CREATE_ITEM_HISTORY_TABLE, [])?;
+    conn.execute(CREATE_ITEM_HISTORY_INDEX, [])?;
+    conn.execute(CREATE_HISTORY_RETENTION_TABLE, [])?;
+    conn.execute(CREATE_ITEM_HISTORY_TRIGGER, [])?;
+    Ok(())
+}
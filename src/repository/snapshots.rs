@@ -0,0 +1,96 @@
+use crate::config::SnapshotLimits;
+use crate::error::db::DatabaseError;
+use crate::model::identity::Identity;
+use crate::model::snapshot::{Snapshot, SnapshotId};
+use crate::repository::functions;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+// NOTE: A read transaction only takes its snapshot at its first read, so one is made right away.
+const BEGIN_SNAPSHOT_STATEMENT: &str = "BEGIN DEFERRED";
+const PIN_SNAPSHOT_QUERY: &str = "SELECT COUNT(*) FROM sqlite_schema";
+
+/// The snapshots opened by `BeginSnapshot`, each holding a read transaction on a read-only connection
+/// of its own.
+///
+/// NOTE: A WAL checkpoint cannot go past the oldest snapshot still open, which is why snapshots are
+/// released once their lease expires, even if they were not ended. The processor releases them before
+/// each task, so a snapshot is never read past its lease, though it stays open until the next task. A snapshot can only be read and ended
+/// by the identity that opened it; other identities do not find it, so that they cannot keep it open or
+/// end it under its owner.
+pub struct Snapshots {
+    database_path: PathBuf,
+    limits: SnapshotLimits,
+    open: HashMap<SnapshotId, OpenSnapshot>,
+}
+
+struct OpenSnapshot {
+    // NOTE: Dropping the connection rolls back its read transaction.
+    conn: Connection,
+    /// `None` when the snapshot was opened by a client that is not authenticated.
+    owner: Option<Identity>,
+    expires_at: Instant,
+}
+
+impl Snapshots {
+    pub fn new(database_path: PathBuf, limits: SnapshotLimits) -> Self {
+        Self {
+            database_path,
+            limits,
+            open: HashMap::new(),
+        }
+    }
+
+    pub fn begin(&mut self, owner: Option<&Identity>) -> Result<Snapshot, DatabaseError> {
+        if self.open.len() >= self.limits.max_snapshots {
+            return Err(DatabaseError::TooManySnapshots);
+        }
+
+        let conn = Connection::open_with_flags(&self.database_path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        functions::register(&conn)?;
+        conn.execute_batch(BEGIN_SNAPSHOT_STATEMENT)?;
+        conn.query_row(PIN_SNAPSHOT_QUERY, [], |_| Ok(()))?;
+
+        let snapshot_id = SnapshotId::generate();
+        self.open.insert(snapshot_id.clone(), OpenSnapshot {
+            conn,
+            owner: owner.cloned(),
+            expires_at: Instant::now() + self.limits.lease,
+        });
+
+        Ok(Snapshot {
+            snapshot_id,
+            lease: self.limits.lease,
+        })
+    }
+
+    /// Returns the connection of the snapshot, and extends its lease.
+    pub fn connection(&mut self, snapshot_id: &SnapshotId, identity: Option<&Identity>) -> Result<&Connection, DatabaseError> {
+        let snapshot = self.open.get_mut(snapshot_id)
+            .filter(|snapshot| snapshot.owner.as_ref() == identity)
+            .ok_or(DatabaseError::SnapshotNotFound)?;
+        snapshot.expires_at = Instant::now() + self.limits.lease;
+        Ok(&snapshot.conn)
+    }
+
+    pub fn end(&mut self, snapshot_id: &SnapshotId, identity: Option<&Identity>) -> Result<(), DatabaseError> {
+        if !self.open.get(snapshot_id).is_some_and(|snapshot| snapshot.owner.as_ref() == identity) {
+            return Err(DatabaseError::SnapshotNotFound);
+        }
+        self.open.remove(snapshot_id);
+        Ok(())
+    }
+
+    /// Releases the snapshots whose lease expired, and returns how many were released.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let before = self.open.len();
+        self.open.retain(|_, snapshot| snapshot.expires_at > now);
+        before - self.open.len()
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.open.len()
+    }
+}
@@ -0,0 +1,56 @@
+use crate::error::db::DatabaseError;
+use crate::model::request_context::{RequestContext, RequestId};
+use crate::repository::Repository;
+use std::future::Future;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically purges the tombstones of the items deleted more than `tombstone_retention` ago, and the
+/// versions older than the history retention of their partition, in batches of `batch_size`, each
+/// executed by the processor as a task of its own.
+pub async fn sweep(repository: Repository, tombstone_retention: Duration, batch_size: usize) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let now = OffsetDateTime::now_utc();
+        purge_in_batches("tombstones", batch_size, |context| {
+            repository.purge_tombstones(context, now - tombstone_retention, batch_size)
+        }).await;
+        purge_in_batches("history", batch_size, |context| {
+            repository.purge_history(context, now, batch_size)
+        }).await;
+    }
+}
+
+/// Purges batches until one is not full, or fails.
+async fn purge_in_batches<F, Fut>(name: &'static str, batch_size: usize, purge: F)
+where
+    F: Fn(RequestContext) -> Fut,
+    Fut: Future<Output=Result<u64, DatabaseError>>,
+{
+    loop {
+        let context = RequestContext {
+            request_id: RequestId::generate(),
+            identity: None,
+            deadline: None,
+        };
+        match purge(context).await {
+            Ok(purged) => {
+                if purged > 0 {
+                    info!(purged, name, "purged expired rows");
+                }
+                if purged < batch_size as u64 {
+                    break;
+                }
+            }
+            Err(e) => {
+                warn!(error = %e, name, "failed to purge expired rows");
+                break;
+            }
+        }
+    }
+}
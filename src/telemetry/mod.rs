@@ -0,0 +1,69 @@
+#[cfg(feature = "otel")]
+mod otel;
+
+use crate::config::{Config, LogFormat};
+use crate::error::app::AppError;
+use tonic::metadata::MetadataMap;
+use tracing::Span;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+/// Keeps the trace exporter alive; pending spans are flushed when it is dropped.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+/// Installs the global tracing subscriber, writing logs to stdout in the configured format.
+///
+/// With the `otel` feature, spans are also exported over OTLP when an endpoint is configured.
+pub fn init(config: &Config) -> Result<Telemetry, AppError> {
+    let filter = EnvFilter::try_new(&config.log_filter)?;
+
+    let fmt_layer = match config.log_format {
+        LogFormat::Text => fmt::layer().boxed(),
+        LogFormat::Json => fmt::layer().json().boxed(),
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer);
+
+    #[cfg(feature = "otel")]
+    {
+        let tracer_provider = config.otlp_endpoint
+            .as_deref()
+            .map(otel::tracer_provider)
+            .transpose()?;
+        let otel_layer = tracer_provider.as_ref().map(otel::layer);
+
+        tracing::subscriber::set_global_default(subscriber.with(otel_layer))?;
+        Ok(Telemetry { tracer_provider })
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        tracing::subscriber::set_global_default(subscriber)?;
+        Ok(Telemetry {})
+    }
+}
+
+/// Makes the span a child of the remote span described by the W3C `traceparent` metadata, if any.
+#[cfg(feature = "otel")]
+pub fn set_remote_parent(span: &Span, metadata: &MetadataMap) {
+    otel::set_remote_parent(span, metadata);
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn set_remote_parent(_span: &Span, _metadata: &MetadataMap) {}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(tracer_provider) = self.tracer_provider.take() {
+            if let Err(e) = tracer_provider.shutdown() {
+                eprintln!("failed to shut down the trace exporter: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// NOTE: Each test binary compiles its own copy of these helpers, and uses only some of them.
+#![allow(dead_code)]
+
+use parapluie::config::{DeleteLimits, SnapshotLimits, ValueLimits};
+use parapluie::grpc::{Acl, RateLimiter, Service};
+use parapluie::metrics::Metrics;
+use parapluie::model::identity::Identity;
+use parapluie::model::key_policy::KeyPolicy;
+use parapluie::model::request_context::{RequestContext, RequestId};
+use parapluie::proto::parapluie as proto;
+use parapluie::repository::{functions, schema, Processor, Repository, Snapshots};
+use prost::Message;
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc as std_mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tonic::codec::{Codec, ProstCodec};
+use tonic::{Request, Streaming};
+use uuid::Uuid;
+
+/// A service backed by a processor running on a fresh database in the temporary directory.
+pub struct TestServer {
+    pub service: Service,
+    /// The repository of the service, for the operations that the service does not expose.
+    pub repository: Repository,
+    pub metrics: Metrics,
+    resume: std_mpsc::Sender<()>,
+    processor: JoinHandle<()>,
+    database: TempDatabase,
+}
+
+/// How the test server is configured, which defaults to small limits.
+pub struct TestOptions {
+    pub acl: Acl,
+    pub value_limits: ValueLimits,
+    pub delete_limits: DeleteLimits,
+    pub snapshot_limits: SnapshotLimits,
+    pub idempotency_window: Duration,
+    /// Keeps the tasks in the queue until `TestServer::resume` is called.
+    pub paused: bool,
+}
+
+impl Default for TestOptions {
+    fn default() -> Self {
+        TestOptions {
+            acl: Acl::default(),
+            value_limits: ValueLimits { max_value_size: 1024, max_large_value_size: 1024, large_value_buffer_size: 1024 },
+            delete_limits: DeleteLimits { batch_size: 10, max_batches: 1 },
+            snapshot_limits: SnapshotLimits { max_snapshots: 1, lease: Duration::from_secs(60) },
+            idempotency_window: Duration::from_secs(60),
+            paused: false,
+        }
+    }
+}
+
+/// Removes the database files once the test is done with them.
+struct TempDatabase(PathBuf);
+
+impl TestServer {
+    pub async fn start() -> TestServer {
+        TestServer::start_with(TestOptions::default()).await
+    }
+
+    pub async fn start_with(options: TestOptions) -> TestServer {
+        let database_path = std::env::temp_dir().join(format!("parapluie-{}.sqlite", Uuid::new_v4()));
+        let metrics = Metrics::new(database_path.clone()).unwrap();
+
+        let (sender, receiver) = mpsc::channel(32);
+        let repository = Repository::new(sender, metrics.clone(), None).await;
+
+        let conn = Connection::open(&database_path).unwrap();
+        conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+        schema::create(&conn).unwrap();
+        functions::register(&conn).unwrap();
+
+        let (resume, resumed) = std_mpsc::channel();
+        if !options.paused {
+            resume.send(()).unwrap();
+        }
+        let snapshots = Snapshots::new(database_path.clone(), options.snapshot_limits);
+        let processor_metrics = metrics.clone();
+        // NOTE: The processor stops once the service, and thus the sender, is dropped.
+        let processor = std::thread::spawn(move || {
+            // NOTE: A server that is never resumed starts processing when it is stopped.
+            let _ = resumed.recv();
+            Processor::new(conn, snapshots, receiver, processor_metrics, None, options.idempotency_window)
+                .blocking_process_tasks()
+                .unwrap();
+        });
+
+        let service = Service::new(
+            repository.clone(),
+            metrics.clone(),
+            options.acl,
+            RateLimiter::new(None),
+            KeyPolicy::default(),
+            options.value_limits,
+            options.delete_limits,
+        );
+
+        TestServer { service, repository, metrics, resume, processor, database: TempDatabase(database_path) }
+    }
+
+    pub fn database_path(&self) -> &Path {
+        &self.database.0
+    }
+
+    /// Lets the processor of a paused server run the tasks queued so far, and those that follow.
+    pub fn resume(&self) {
+        self.resume.send(()).unwrap();
+    }
+
+    /// Stops the processor, and waits until it has dropped its last task, along with the spans the
+    /// task keeps open.
+    pub fn stop(self) {
+        let TestServer { service, repository, resume, processor, database, .. } = self;
+        drop((service, repository, resume));
+        processor.join().unwrap();
+        drop(database);
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub fn context() -> RequestContext {
+    RequestContext {
+        request_id: RequestId::generate(),
+        identity: None,
+        deadline: None,
+    }
+}
+
+/// Wraps the message in a request authenticated with the token of this name.
+pub fn request_as<T>(name: &str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request.extensions_mut().insert(Identity::Token { name: name.to_string() });
+    request
+}
+
+pub fn partition_key(key: &str) -> proto::PartitionKey {
+    proto::PartitionKey { value: Some(proto::partition_key::Value::Text(key.to_string())) }
+}
+
+pub fn sort_key(key: &str) -> proto::SortKey {
+    proto::SortKey { value: Some(proto::sort_key::Value::Text(key.to_string())) }
+}
+
+pub fn set_request(partition_key: &str, sort_key: &str, value: &[u8]) -> proto::SetRequest {
+    proto::SetRequest {
+        partition_key: Some(self::partition_key(partition_key)),
+        set_values: vec![proto::SetValue {
+            sort_key: Some(self::sort_key(sort_key)),
+            value: value.to_vec(),
+            ..Default::default()
+        }],
+        idempotency_key: String::new(),
+    }
+}
+
+pub fn get_request(partition_key: &str, sort_key: &str) -> proto::GetRequest {
+    proto::GetRequest {
+        partition_key: Some(self::partition_key(partition_key)),
+        sort_key: Some(self::sort_key(sort_key)),
+        ..Default::default()
+    }
+}
+
+/// Frames the messages as the body of a client stream, the way a gRPC client sends them.
+pub fn streaming<T: Message + Default + 'static>(messages: Vec<T>) -> Streaming<T> {
+    let mut body = Vec::new();
+    for message in messages {
+        let encoded = message.encode_to_vec();
+        body.push(0);
+        body.extend((encoded.len() as u32).to_be_bytes());
+        body.extend(encoded);
+    }
+    Streaming::new_request(ProstCodec::<T, T>::default().decoder(), axum::body::Body::from(body), None, None)
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::{partition_key, set_request, sort_key, TestOptions, TestServer};
+use parapluie::config::DeleteLimits;
+use parapluie::proto::parapluie as proto;
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDb;
+use tonic::Request;
+
+const PARTITION_KEY: &str = "orders";
+
+fn included(key: &str) -> Option<proto::Bound> {
+    Some(proto::Bound { bound: Some(proto::bound::Bound::Included(sort_key(key))) })
+}
+
+fn excluded(key: &str) -> Option<proto::Bound> {
+    Some(proto::Bound { bound: Some(proto::bound::Bound::Excluded(sort_key(key))) })
+}
+
+fn sort_key_text(key: Option<proto::SortKey>) -> Option<String> {
+    match key?.value? {
+        proto::sort_key::Value::Text(text) => Some(text),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn deletions_of_ranges_larger_than_the_batches_are_continued() {
+    let delete_limits = DeleteLimits { batch_size: 2, max_batches: 2 };
+    let server = TestServer::start_with(TestOptions { delete_limits, ..TestOptions::default() }).await;
+    for index in 0..12 {
+        server.service.set(Request::new(set_request(PARTITION_KEY, &format!("k{:02}", index), b"value"))).await.unwrap();
+    }
+
+    // NOTE: The range holds k01 to k10, more than the 4 items deleted by each call.
+    let range = proto::Range { start: included("k01"), end: excluded("k11") };
+    let mut responses = Vec::new();
+    let mut start_after = None;
+    loop {
+        let request = proto::DeleteRangeRequest { partition_key: Some(partition_key(PARTITION_KEY)), range: Some(range.clone()), start_after };
+        let response = server.service.delete_range(Request::new(request)).await.unwrap().into_inner();
+        start_after = response.next_start_after.clone();
+        responses.push((response.deleted_count, sort_key_text(response.next_start_after)));
+        if start_after.is_none() {
+            break;
+        }
+    }
+
+    let list = proto::ListRequest { partition_key: Some(partition_key(PARTITION_KEY)), page_size: 20, ..Default::default() };
+    let items = server.service.list(Request::new(list)).await.unwrap().into_inner().items;
+    assert_eq!(responses, vec![
+        (4, Some("k04".to_string())),
+        (4, Some("k08".to_string())),
+        (2, None),
+    ]);
+    let remaining: Vec<_> = items.into_iter().map(|item| sort_key_text(item.sort_key).unwrap()).collect();
+    assert_eq!(remaining, vec!["k00", "k11"]);
+    server.stop();
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::{partition_key, set_request, sort_key, streaming, TestServer};
+use parapluie::proto::parapluie as proto;
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDb;
+use rusqlite::Connection;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+use tonic::{Code, Request, Status};
+
+const PARTITION_KEY: &str = "orders";
+
+fn put_large_request(chunks: &[&[u8]], write_condition: Option<proto::WriteCondition>) -> Request<tonic::Streaming<proto::PutLargeRequest>> {
+    let messages = chunks.iter()
+        .enumerate()
+        .map(|(index, chunk)| match index {
+            0 => proto::PutLargeRequest {
+                partition_key: Some(partition_key(PARTITION_KEY)),
+                sort_key: Some(sort_key("order")),
+                write_condition: write_condition.clone(),
+                chunk: chunk.to_vec(),
+            },
+            _ => proto::PutLargeRequest { chunk: chunk.to_vec(), ..Default::default() },
+        })
+        .collect();
+    Request::new(streaming(messages))
+}
+
+fn get_large_request() -> Request<proto::GetLargeRequest> {
+    Request::new(proto::GetLargeRequest { partition_key: Some(partition_key(PARTITION_KEY)), sort_key: Some(sort_key("order")) })
+}
+
+async fn get_large(server: &TestServer) -> Vec<Result<proto::GetLargeResponse, Status>> {
+    server.service.get_large(get_large_request())
+        .await
+        .unwrap()
+        .into_inner()
+        .collect()
+        .await
+}
+
+/// Waits until the chunks of the failed uploads are dropped, which a task of their own does.
+async fn wait_for_uploads_to_be_dropped(server: &TestServer) {
+    let conn = Connection::open(server.database_path()).unwrap();
+    for _ in 0..100 {
+        let count: u64 = conn.query_row("SELECT count(*) FROM large_value_upload_chunk", [], |row| row.get(0)).unwrap();
+        if count == 0 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the chunks of the failed uploads were not dropped");
+}
+
+#[tokio::test]
+async fn large_values_are_written_and_read_in_chunks() {
+    let server = TestServer::start().await;
+
+    let response = server.service.put_large(put_large_request(&[b"ab", b"cd", b"", b"ef"], None)).await.unwrap();
+    let messages = get_large(&server).await.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+
+    assert_eq!(response.into_inner().version, 1);
+    let item = messages[0].item.as_ref().unwrap();
+    assert_eq!((item.version, item.large_value_size), (1, 6));
+    let chunks: Vec<_> = messages[1..].iter().map(|message| message.chunk.as_slice()).collect();
+    assert_eq!(chunks, vec![b"ab", b"cd", b"ef"]);
+    server.stop();
+}
+
+#[tokio::test]
+async fn chunks_of_failed_uploads_are_dropped() {
+    let server = TestServer::start().await;
+    let missing = proto::WriteCondition { version_equals: Some(5), ..Default::default() };
+
+    let condition_not_met = server.service.put_large(put_large_request(&[b"ab", b"cd"], Some(missing))).await.unwrap_err();
+    let too_large = server.service.put_large(put_large_request(&[&[0; 1000], &[0; 100]], None)).await.unwrap_err();
+
+    assert_eq!(condition_not_met.code(), Code::FailedPrecondition);
+    assert_eq!(too_large.code(), Code::InvalidArgument);
+    assert_eq!(server.service.get_large(get_large_request()).await.err().map(|status| status.code()), Some(Code::NotFound));
+    wait_for_uploads_to_be_dropped(&server).await;
+    server.stop();
+}
+
+#[tokio::test]
+async fn reads_of_large_values_written_meanwhile_are_aborted() {
+    let server = TestServer::start().await;
+    server.service.put_large(put_large_request(&[b"a", b"b", b"c", b"d"], None)).await.unwrap();
+    let mut stream = server.service.get_large(get_large_request()).await.unwrap().into_inner();
+
+    assert!(stream.next().await.unwrap().unwrap().item.is_some());
+    server.service.set(Request::new(set_request(PARTITION_KEY, "order", b"value"))).await.unwrap();
+    let rest: Vec<_> = stream.collect().await;
+
+    // NOTE: The chunks read before the write are still sent.
+    assert!(rest.len() < 4, "{:?}", rest);
+    assert_eq!(rest.last().unwrap().as_ref().unwrap_err().code(), Code::Aborted);
+    server.stop();
+}
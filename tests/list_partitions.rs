@@ -0,0 +1,73 @@
+mod common;
+
+use common::{context, partition_key, request_as, TestOptions, TestServer};
+use parapluie::grpc::Acl;
+use parapluie::model::key::Key;
+use parapluie::model::partition_key::PartitionKey;
+use parapluie::model::set_value::SetValue;
+use parapluie::model::sort_key::SortKey;
+use parapluie::model::write_condition::WriteCondition;
+use parapluie::model::write_mode::WriteMode;
+use parapluie::proto::parapluie as proto;
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDb;
+use uuid::Uuid;
+
+/// Loads an ACL granting `alice` read access to these partitions only.
+fn acl_reading(partition_keys: &[&str]) -> Acl {
+    let path = std::env::temp_dir().join(format!("parapluie-{}.acl", Uuid::new_v4()));
+    let content: String = partition_keys.iter().map(|key| format!("read {} token:alice\n", key)).collect();
+    std::fs::write(&path, content).unwrap();
+    let acl = Acl::load(&path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    acl
+}
+
+async fn create_partition(server: &TestServer, key: &str) {
+    let set_value = SetValue {
+        sort_key: SortKey(Key::Text("item".to_string())),
+        sort_key_is_tuple: false,
+        write_condition: WriteCondition::default(),
+        write_mode: WriteMode::Upsert,
+        value: b"value".to_vec(),
+    };
+    server.repository.set(context(), PartitionKey(Key::Text(key.to_string())), vec![set_value], None).await.unwrap();
+}
+
+fn text(key: Option<&proto::PartitionKey>) -> Option<String> {
+    match key?.value.as_ref()? {
+        proto::partition_key::Value::Text(text) => Some(text.clone()),
+        proto::partition_key::Value::Binary(_) => None,
+    }
+}
+
+#[tokio::test]
+async fn partitions_the_caller_may_not_read_are_never_returned() {
+    let readable = ["p2", "p5", "p8"];
+    let server = TestServer::start_with(TestOptions { acl: acl_reading(&readable), ..TestOptions::default() }).await;
+    for index in 1..=9 {
+        create_partition(&server, &format!("p{}", index)).await;
+    }
+
+    let mut pages = Vec::new();
+    let mut start_after = None;
+    loop {
+        let request = proto::ListPartitionsRequest { page_size: 2, start_after, ..Default::default() };
+        let response = server.service.list_partitions(request_as("alice", request)).await.unwrap().into_inner();
+        let keys: Vec<_> = response.partitions.iter().map(|partition| text(partition.partition_key.as_ref()).unwrap()).collect();
+        let next_start_after = text(response.next_start_after.as_ref());
+        pages.push((keys, next_start_after));
+        match response.next_start_after {
+            Some(next_start_after) => start_after = Some(next_start_after),
+            None => break,
+        }
+    }
+
+    assert_eq!(pages, vec![
+        (vec!["p2".to_string(), "p5".to_string()], Some("p5".to_string())),
+        (vec!["p8".to_string()], None),
+    ]);
+    let first = proto::ListPartitionsRequest { page_size: 1, start_after: Some(partition_key("p5")), ..Default::default() };
+    let response = server.service.list_partitions(request_as("alice", first)).await.unwrap().into_inner();
+    assert_eq!(text(response.next_start_after.as_ref()), Some("p8".to_string()));
+    server.stop();
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{context, set_request, TestServer};
+use parapluie::metrics::{self, Metrics};
+use parapluie::model::get_options::GetOptions;
+use parapluie::model::key::Key;
+use parapluie::model::partition_key::PartitionKey;
+use parapluie::model::sort_key::SortKey;
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDb;
+use parapluie::repository::Repository;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tonic::Request;
+
+const METRIC_FAMILIES: [&str; 5] = [
+    "parapluie_grpc_requests_total",
+    "parapluie_grpc_request_duration_seconds",
+    "parapluie_processor_queue_depth",
+    "parapluie_transactions_total",
+    "parapluie_sqlite_file_size_bytes",
+];
+
+#[tokio::test]
+async fn metrics_endpoint_exposes_request_processor_and_sqlite_metrics() {
+    let server = TestServer::start().await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(metrics::serve_on(listener, server.metrics.clone()));
+
+    server.service.set(Request::new(set_request("orders", "1", b"value")))
+        .await
+        .unwrap();
+
+    let body = scrape(&addr.to_string()).await;
+
+    for family in METRIC_FAMILIES {
+        assert!(body.contains(&format!("# TYPE {} ", family)), "missing {} in:\n{}", family, body);
+    }
+    assert!(body.contains(r#"parapluie_grpc_requests_total{code="Ok",method="Set"} 1"#), "{}", body);
+    assert!(body.contains(r#"parapluie_transactions_total{outcome="commit"} 1"#), "{}", body);
+    assert!(body.contains(r#"parapluie_sqlite_file_size_bytes{file="wal"}"#), "{}", body);
+}
+
+#[tokio::test]
+async fn calls_cancelled_while_the_queue_is_full_are_not_counted_in_its_depth() {
+    let metrics = Metrics::new(std::env::temp_dir().join("parapluie-unused.sqlite")).unwrap();
+    // NOTE: Nothing reads the queue, so the first call fills it and the second waits for a slot.
+    let (sender, _receiver) = mpsc::channel(1);
+    let repository = Repository::new(sender, metrics.clone(), None).await;
+
+    for _ in 0..2 {
+        let get = repository.get(context(), PartitionKey(Key::Text("orders".to_string())), SortKey(Key::Text("1".to_string())), GetOptions::default());
+        assert!(timeout(Duration::from_millis(50), get).await.is_err());
+    }
+
+    let body = metrics.encode().unwrap();
+    assert!(body.contains("parapluie_processor_queue_depth 1\n"), "{}", body);
+}
+
+/// Sends `GET /metrics` over a plain connection, and returns the body of the response.
+async fn scrape(addr: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET /metrics HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", addr);
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    response.split_once("\r\n\r\n").unwrap().1.to_string()
+}
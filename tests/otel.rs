@@ -0,0 +1,101 @@
+#![cfg(feature = "otel")]
+
+mod common;
+
+use common::{set_request, TestServer};
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
+use opentelemetry_proto::tonic::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+use opentelemetry_proto::tonic::trace::v1::Span;
+use parapluie::config::Config;
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDb;
+use parapluie::telemetry;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+/// Collects the spans exported to it, standing in for an OTLP collector.
+#[derive(Clone, Default)]
+struct Collector {
+    spans: Arc<Mutex<Vec<Span>>>,
+}
+
+#[tonic::async_trait]
+impl TraceService for Collector {
+    async fn export(&self, request: Request<ExportTraceServiceRequest>) -> Result<Response<ExportTraceServiceResponse>, Status> {
+        let spans = request.into_inner()
+            .resource_spans
+            .into_iter()
+            .flat_map(|resource_spans| resource_spans.scope_spans)
+            .flat_map(|scope_spans| scope_spans.spans);
+        self.spans.lock().unwrap().extend(spans);
+
+        Ok(Response::new(ExportTraceServiceResponse { partial_success: None }))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spans_are_exported_under_the_incoming_trace() {
+    let collector = Collector::default();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::builder()
+        .add_service(TraceServiceServer::new(collector.clone()))
+        .serve_with_incoming(TcpListenerStream::new(listener)));
+
+    std::env::set_var("PARAPLUIE_OTLP_ENDPOINT", format!("http://{}", addr));
+    let config = Config::from_env().unwrap();
+    let telemetry = telemetry::init(&config).unwrap();
+
+    let server = TestServer::start().await;
+    let mut request = Request::new(set_request("orders", "1", b"value"));
+    request.metadata_mut().insert("traceparent", format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID).parse().unwrap());
+    server.service.set(request).await.unwrap();
+
+    // NOTE: Spans are only exported once closed, and the task keeps its spans open until the processor
+    // drops it. Dropping the telemetry then flushes the spans, and blocks until they are exported.
+    tokio::task::spawn_blocking(move || {
+        server.stop();
+        drop(telemetry);
+    }).await.unwrap();
+
+    let spans = collector.spans.lock().unwrap().clone();
+    let rpc = find_span(&spans, |name| name == "rpc");
+    assert_eq!(hex(&rpc.trace_id), TRACE_ID);
+    assert_eq!(hex(&rpc.parent_span_id), PARENT_SPAN_ID);
+
+    for name in ["processor.task", "sql."] {
+        let span = find_span(&spans, |span_name| span_name.starts_with(name));
+        assert_eq!(hex(&span.trace_id), TRACE_ID, "trace ID of {}", span.name);
+        assert!(descends_from(&spans, span, &rpc.span_id), "{} is not a descendant of the rpc span", span.name);
+    }
+}
+
+fn find_span(spans: &[Span], predicate: impl Fn(&str) -> bool) -> &Span {
+    spans.iter()
+        .find(|span| predicate(&span.name))
+        .unwrap_or_else(|| panic!("span not exported, got {:?}", spans.iter().map(|span| &span.name).collect::<Vec<_>>()))
+}
+
+/// Whether one of the ancestors of the span, among the exported ones, has the given ID.
+fn descends_from(spans: &[Span], span: &Span, ancestor_id: &[u8]) -> bool {
+    let mut parent_id = &span.parent_span_id;
+    while !parent_id.is_empty() {
+        if parent_id == ancestor_id {
+            return true;
+        }
+        match spans.iter().find(|span| &span.span_id == parent_id) {
+            Some(parent) => parent_id = &parent.parent_span_id,
+            None => return false,
+        }
+    }
+    false
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
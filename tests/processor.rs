@@ -0,0 +1,214 @@
+mod common;
+
+use common::{context, get_request, request_as, set_request, TestOptions, TestServer};
+use parapluie::config::ValueLimits;
+use parapluie::error::db::DatabaseError;
+use parapluie::model::history_retention::HistoryRetention;
+use parapluie::model::key::Key;
+use parapluie::model::large_item::UploadId;
+use parapluie::model::partition_key::PartitionKey;
+use parapluie::model::patch::Patch;
+use parapluie::model::request_context::RequestContext;
+use parapluie::model::set_value::SetValue;
+use parapluie::model::sort_key::SortKey;
+use parapluie::model::sort_key_range::SortKeyRange;
+use parapluie::model::write_condition::WriteCondition;
+use parapluie::model::write_mode::WriteMode;
+use parapluie::proto::parapluie as proto;
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDb;
+use std::collections::Bound;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tokio::time::timeout;
+use tonic::{Code, Request};
+
+const PARTITION_KEY: &str = "orders";
+
+fn set_value(sort_key: &str) -> Vec<SetValue> {
+    vec![SetValue {
+        sort_key: SortKey(Key::Text(sort_key.to_string())),
+        sort_key_is_tuple: false,
+        write_condition: WriteCondition::default(),
+        write_mode: WriteMode::Upsert,
+        value: b"value".to_vec(),
+    }]
+}
+
+fn partition_key() -> PartitionKey {
+    PartitionKey(Key::Text(PARTITION_KEY.to_string()))
+}
+
+async fn get(server: &TestServer, sort_key: &str) -> Result<proto::Item, Code> {
+    server.service.get(Request::new(get_request(PARTITION_KEY, sort_key)))
+        .await
+        .map(|response| response.into_inner().item.unwrap())
+        .map_err(|status| status.code())
+}
+
+#[tokio::test]
+async fn tasks_past_their_deadline_are_rejected_without_being_committed() {
+    let server = TestServer::start().await;
+    let expired = RequestContext { deadline: Some(Instant::now()), ..context() };
+
+    let result = server.repository.set(expired, partition_key(), set_value("expired"), None).await;
+    server.repository.set(context(), partition_key(), set_value("current"), None).await.unwrap();
+
+    assert!(matches!(result, Err(DatabaseError::DeadlineExceeded)));
+    assert_eq!(get(&server, "expired").await.err(), Some(Code::NotFound));
+    assert!(get(&server, "current").await.is_ok());
+    server.stop();
+}
+
+#[tokio::test]
+async fn tasks_whose_caller_stopped_waiting_are_dropped_without_being_committed() {
+    let server = TestServer::start_with(TestOptions { paused: true, ..TestOptions::default() }).await;
+
+    let cancelled = server.repository.set(context(), partition_key(), set_value("cancelled"), None);
+    assert!(timeout(Duration::from_millis(50), cancelled).await.is_err());
+    server.resume();
+    server.repository.set(context(), partition_key(), set_value("current"), None).await.unwrap();
+
+    assert_eq!(get(&server, "cancelled").await.err(), Some(Code::NotFound));
+    assert!(get(&server, "current").await.is_ok());
+    server.stop();
+}
+
+#[tokio::test]
+async fn updates_whose_patched_value_is_too_large_are_rejected() {
+    let value_limits = ValueLimits { max_value_size: 16, ..TestOptions::default().value_limits };
+    let server = TestServer::start_with(TestOptions { value_limits, ..TestOptions::default() }).await;
+    server.service.set(Request::new(set_request(PARTITION_KEY, "order", br#"{"a":1}"#))).await.unwrap();
+
+    let patch = Patch::Merge(serde_json::json!({ "b": "x".repeat(16) }));
+    let sort_key = SortKey(Key::Text("order".to_string()));
+    let result = server.repository.update(context(), partition_key(), sort_key, WriteCondition::default(), patch, 16).await;
+
+    assert!(matches!(result, Err(DatabaseError::ValueTooLarge { size: 30, max_size: 16 })));
+    assert_eq!(get(&server, "order").await.unwrap().value, br#"{"a":1}"#.to_vec());
+    server.stop();
+}
+
+#[tokio::test]
+async fn history_keeps_the_size_of_large_values() {
+    let server = TestServer::start().await;
+    let sort_key = SortKey(Key::Text("order".to_string()));
+    server.repository.set_history_retention(context(), partition_key(), Some(HistoryRetention::default())).await.unwrap();
+    let upload_id = UploadId::generate();
+    for (chunk_index, chunk) in [vec![0; 3], vec![0; 4]].into_iter().enumerate() {
+        server.repository.append_large_chunk(context(), upload_id.clone(), chunk_index as u64, chunk).await.unwrap();
+    }
+    server.repository.put_large(context(), partition_key(), sort_key.clone(), false, WriteCondition::default(), upload_id).await.unwrap();
+    server.service.set(Request::new(set_request(PARTITION_KEY, "order", b"value"))).await.unwrap();
+
+    let history = server.repository.get_history(context(), partition_key(), sort_key, None, 10).await.unwrap();
+    let version = server.service.get(Request::new(proto::GetRequest { version: 1, ..get_request(PARTITION_KEY, "order") }))
+        .await
+        .unwrap()
+        .into_inner()
+        .item
+        .unwrap();
+
+    assert_eq!(history.iter().map(|item| item.large_value_size).collect::<Vec<_>>(), vec![Some(7)]);
+    assert_eq!(version.large_value_size, 7);
+    server.stop();
+}
+
+fn idempotent_set_request(value: &[u8]) -> proto::SetRequest {
+    proto::SetRequest { idempotency_key: "attempt".to_string(), ..set_request(PARTITION_KEY, "order", value) }
+}
+
+#[tokio::test]
+async fn retries_carrying_an_idempotency_key_are_replayed() {
+    let server = TestServer::start().await;
+
+    for _ in 0..2 {
+        server.service.set(Request::new(idempotent_set_request(b"value"))).await.unwrap();
+    }
+
+    assert_eq!(get(&server, "order").await.unwrap().version, 1);
+    let metrics = server.metrics.encode().unwrap();
+    assert!(metrics.contains(r#"parapluie_transactions_total{outcome="commit"} 2"#), "{}", metrics);
+    server.stop();
+}
+
+#[tokio::test]
+async fn idempotency_keys_reused_by_another_request_are_rejected() {
+    let server = TestServer::start().await;
+    server.service.set(Request::new(idempotent_set_request(b"value"))).await.unwrap();
+
+    let other_value = server.service.set(Request::new(idempotent_set_request(b"other"))).await.unwrap_err();
+    let other_caller = server.service.set(request_as("other", idempotent_set_request(b"value"))).await.unwrap_err();
+
+    assert_eq!(other_value.code(), Code::InvalidArgument);
+    assert_eq!(other_caller.code(), Code::InvalidArgument);
+    let item = get(&server, "order").await.unwrap();
+    assert_eq!((item.value, item.version), (b"value".to_vec(), 1));
+    let metrics = server.metrics.encode().unwrap();
+    assert!(metrics.contains(r#"parapluie_transactions_total{outcome="rollback"} 2"#), "{}", metrics);
+    server.stop();
+}
+
+#[tokio::test]
+async fn idempotency_keys_are_forgotten_after_the_idempotency_window() {
+    let idempotency_window = Duration::from_millis(1);
+    let server = TestServer::start_with(TestOptions { idempotency_window, ..TestOptions::default() }).await;
+    server.service.set(Request::new(idempotent_set_request(b"value"))).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    server.service.set(Request::new(idempotent_set_request(b"other"))).await.unwrap();
+
+    let item = get(&server, "order").await.unwrap();
+    assert_eq!((item.value, item.version), (b"other".to_vec(), 2));
+    server.stop();
+}
+
+async fn delete(server: &TestServer, sort_key: &str) {
+    let sort_key = SortKey(Key::Text(sort_key.to_string()));
+    let range = SortKeyRange::Bounds(Bound::Included(sort_key.clone()), Bound::Included(sort_key));
+    let batch = server.repository.delete_range(context(), partition_key(), range, None, 10).await.unwrap();
+    assert_eq!(batch.deleted_count, 1);
+}
+
+async fn get_deleted(server: &TestServer, sort_key: &str) -> Result<proto::Item, Code> {
+    server.service.get(Request::new(proto::GetRequest { include_deleted: true, ..get_request(PARTITION_KEY, sort_key) }))
+        .await
+        .map(|response| response.into_inner().item.unwrap())
+        .map_err(|status| status.code())
+}
+
+#[tokio::test]
+async fn deleted_items_are_restored_with_a_new_version() {
+    let server = TestServer::start().await;
+    server.service.set(Request::new(set_request(PARTITION_KEY, "order", b"value"))).await.unwrap();
+
+    delete(&server, "order").await;
+    let deleted = get(&server, "order").await;
+    let undelete = proto::UndeleteRequest { partition_key: Some(common::partition_key(PARTITION_KEY)), sort_key: Some(common::sort_key("order")) };
+    let restored = server.service.undelete(Request::new(undelete)).await.unwrap().into_inner().item.unwrap();
+
+    assert_eq!(deleted.err(), Some(Code::NotFound));
+    assert_eq!((restored.value, restored.version, restored.deleted_at), (b"value".to_vec(), 3, None));
+    let item = get(&server, "order").await.unwrap();
+    assert_eq!((item.value, item.version), (b"value".to_vec(), 3));
+    server.stop();
+}
+
+#[tokio::test]
+async fn only_tombstones_deleted_before_the_cutoff_are_purged() {
+    let server = TestServer::start().await;
+    for sort_key in ["old", "new"] {
+        server.service.set(Request::new(set_request(PARTITION_KEY, sort_key, b"value"))).await.unwrap();
+    }
+
+    delete(&server, "old").await;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let deleted_before = OffsetDateTime::now_utc();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    delete(&server, "new").await;
+    let purged = server.repository.purge_tombstones(context(), deleted_before, 10).await.unwrap();
+
+    assert_eq!(purged, 1);
+    assert_eq!(get_deleted(&server, "old").await.err(), Some(Code::NotFound));
+    assert!(get_deleted(&server, "new").await.unwrap().deleted_at.is_some());
+    server.stop();
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::{get_request, request_as, set_request, TestOptions, TestServer};
+use parapluie::config::SnapshotLimits;
+use parapluie::proto::parapluie as proto;
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDb;
+use std::time::Duration;
+use tonic::{Code, Request};
+
+const PARTITION_KEY: &str = "orders";
+
+async fn begin_snapshot(server: &TestServer, owner: &str) -> Result<String, Code> {
+    server.service.begin_snapshot(request_as(owner, proto::BeginSnapshotRequest {}))
+        .await
+        .map(|response| response.into_inner().snapshot_id)
+        .map_err(|status| status.code())
+}
+
+async fn end_snapshot(server: &TestServer, owner: &str, snapshot_id: &str) -> Result<(), Code> {
+    server.service.end_snapshot(request_as(owner, proto::EndSnapshotRequest { snapshot_id: snapshot_id.to_string() }))
+        .await
+        .map(|_| ())
+        .map_err(|status| status.code())
+}
+
+async fn get_in_snapshot(server: &TestServer, owner: &str, snapshot_id: &str) -> Result<Vec<u8>, Code> {
+    let request = proto::GetRequest { snapshot_id: snapshot_id.to_string(), ..get_request(PARTITION_KEY, "order") };
+    server.service.get(request_as(owner, request))
+        .await
+        .map(|response| response.into_inner().item.unwrap().value)
+        .map_err(|status| status.code())
+}
+
+#[tokio::test]
+async fn snapshots_are_only_found_by_their_owner() {
+    let server = TestServer::start().await;
+    server.service.set(Request::new(set_request(PARTITION_KEY, "order", b"value"))).await.unwrap();
+
+    let snapshot_id = begin_snapshot(&server, "owner").await.unwrap();
+
+    assert_eq!(get_in_snapshot(&server, "other", &snapshot_id).await, Err(Code::NotFound));
+    assert_eq!(end_snapshot(&server, "other", &snapshot_id).await, Err(Code::NotFound));
+    assert_eq!(get_in_snapshot(&server, "owner", &snapshot_id).await, Ok(b"value".to_vec()));
+    assert_eq!(end_snapshot(&server, "owner", &snapshot_id).await, Ok(()));
+    // NOTE: At most one snapshot is open at a time, so this fails unless the first one was released.
+    assert!(begin_snapshot(&server, "owner").await.is_ok());
+    server.stop();
+}
+
+#[tokio::test]
+async fn snapshots_do_not_see_later_writes() {
+    let server = TestServer::start().await;
+    server.service.set(Request::new(set_request(PARTITION_KEY, "order", b"value"))).await.unwrap();
+    let snapshot_id = begin_snapshot(&server, "owner").await.unwrap();
+
+    server.service.set(Request::new(set_request(PARTITION_KEY, "order", b"other"))).await.unwrap();
+
+    assert_eq!(get_in_snapshot(&server, "owner", &snapshot_id).await, Ok(b"value".to_vec()));
+    let latest = server.service.get(Request::new(get_request(PARTITION_KEY, "order"))).await.unwrap().into_inner();
+    assert_eq!(latest.item.unwrap().value, b"other".to_vec());
+    server.stop();
+}
+
+#[tokio::test]
+async fn snapshots_are_released_once_their_lease_expires() {
+    let snapshot_limits = SnapshotLimits { max_snapshots: 1, lease: Duration::from_millis(200) };
+    let server = TestServer::start_with(TestOptions { snapshot_limits, ..TestOptions::default() }).await;
+    server.service.set(Request::new(set_request(PARTITION_KEY, "order", b"value"))).await.unwrap();
+    let snapshot_id = begin_snapshot(&server, "owner").await.unwrap();
+
+    // NOTE: Each read extends the lease, so the snapshot outlives its first lease.
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(get_in_snapshot(&server, "owner", &snapshot_id).await, Ok(b"value".to_vec()));
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert_eq!(get_in_snapshot(&server, "owner", &snapshot_id).await, Err(Code::NotFound));
+    assert!(begin_snapshot(&server, "owner").await.is_ok());
+    server.stop();
+}
+
+#[tokio::test]
+async fn snapshots_beyond_the_maximum_are_refused() {
+    let snapshot_limits = SnapshotLimits { max_snapshots: 2, ..TestOptions::default().snapshot_limits };
+    let server = TestServer::start_with(TestOptions { snapshot_limits, ..TestOptions::default() }).await;
+    let first = begin_snapshot(&server, "owner").await.unwrap();
+    begin_snapshot(&server, "other").await.unwrap();
+
+    let refused = begin_snapshot(&server, "owner").await;
+    end_snapshot(&server, "owner", &first).await.unwrap();
+
+    assert_eq!(refused, Err(Code::ResourceExhausted));
+    assert!(begin_snapshot(&server, "owner").await.is_ok());
+    server.stop();
+}
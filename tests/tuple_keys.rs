@@ -0,0 +1,74 @@
+mod common;
+
+use common::{partition_key, TestServer};
+use parapluie::model::tuple::{self, TupleElement};
+use parapluie::proto::parapluie as proto;
+use parapluie::proto::parapluie::parapluie_db_server::ParapluieDb;
+use tonic::Request;
+
+const PARTITION_KEY: &str = "orders";
+
+fn tuple_key(elements: &[&str]) -> proto::TupleKey {
+    let elements = elements.iter()
+        .map(|element| proto::TupleElement { value: Some(proto::tuple_element::Value::Text(element.to_string())) })
+        .collect();
+    proto::TupleKey { elements }
+}
+
+fn tuple_sort_key(elements: &[&str]) -> proto::SortKey {
+    proto::SortKey { value: Some(proto::sort_key::Value::Tuple(tuple_key(elements))) }
+}
+
+/// The bytes of the encoding of the tuple, written as a binary key.
+fn binary_sort_key(elements: &[&str]) -> proto::SortKey {
+    let elements: Vec<_> = elements.iter().map(|element| TupleElement::String(element.to_string())).collect();
+    proto::SortKey { value: Some(proto::sort_key::Value::Binary(tuple::encode(&elements))) }
+}
+
+async fn set(server: &TestServer, sort_key: proto::SortKey) {
+    let request = proto::SetRequest {
+        partition_key: Some(partition_key(PARTITION_KEY)),
+        set_values: vec![proto::SetValue { sort_key: Some(sort_key), value: b"value".to_vec(), ..Default::default() }],
+        idempotency_key: String::new(),
+    };
+    server.service.set(Request::new(request)).await.unwrap();
+}
+
+async fn get_tuple(server: &TestServer, sort_key: proto::SortKey) -> Option<proto::TupleKey> {
+    let request = proto::GetRequest { partition_key: Some(partition_key(PARTITION_KEY)), sort_key: Some(sort_key), ..Default::default() };
+    server.service.get(Request::new(request)).await.unwrap().into_inner().item.unwrap().sort_key_tuple
+}
+
+#[tokio::test]
+async fn only_sort_keys_written_as_tuples_are_decoded() {
+    let server = TestServer::start().await;
+    set(&server, tuple_sort_key(&["tuple"])).await;
+    set(&server, binary_sort_key(&["binary"])).await;
+
+    let listed = server.service.list(Request::new(proto::ListRequest { partition_key: Some(partition_key(PARTITION_KEY)), page_size: 10, ..Default::default() }))
+        .await
+        .unwrap()
+        .into_inner()
+        .items;
+
+    assert_eq!(get_tuple(&server, tuple_sort_key(&["tuple"])).await, Some(tuple_key(&["tuple"])));
+    assert_eq!(get_tuple(&server, tuple_sort_key(&["binary"])).await, None);
+    let tuples: Vec<_> = listed.into_iter().map(|item| item.sort_key_tuple).collect();
+    assert_eq!(tuples, vec![None, Some(tuple_key(&["tuple"]))]);
+    server.stop();
+}
+
+#[tokio::test]
+async fn sort_keys_are_decoded_as_they_were_last_written() {
+    let server = TestServer::start().await;
+    set(&server, tuple_sort_key(&["order"])).await;
+    set(&server, binary_sort_key(&["order"])).await;
+    let rewritten_as_bytes = get_tuple(&server, tuple_sort_key(&["order"])).await;
+
+    let request = proto::IncrementRequest { partition_key: Some(partition_key(PARTITION_KEY)), sort_key: Some(tuple_sort_key(&["counter"])), delta: 1, initial_value: 0 };
+    server.service.increment(Request::new(request)).await.unwrap();
+
+    assert_eq!(rewritten_as_bytes, None);
+    assert_eq!(get_tuple(&server, binary_sort_key(&["counter"])).await, Some(tuple_key(&["counter"])));
+    server.stop();
+}